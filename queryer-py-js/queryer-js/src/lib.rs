@@ -0,0 +1,40 @@
+use napi::{bindgen_prelude::*, Env, JsObject};
+use napi_derive::napi;
+
+#[napi(object)]
+pub struct QueryOptions {
+    pub output: Option<String>,
+}
+
+#[napi]
+pub fn example_sql() -> String {
+    queryer::example_sql()
+}
+
+// Mirrors queryer-py: an unsupported output throws a TypeError right away,
+// while a failing query rejects the returned promise with an Error
+#[napi(ts_return_type = "Promise<string | Buffer>")]
+pub fn query(env: Env, sql: String, options: Option<QueryOptions>) -> Result<JsObject> {
+    let output = options
+        .and_then(|o| o.output)
+        .unwrap_or_else(|| "csv".to_string());
+
+    if !matches!(output.as_str(), "csv" | "json" | "arrow") {
+        let msg = format!("Output type {} not supported", output);
+        env.throw_type_error(&msg, None)?;
+        return Err(Error::new(Status::PendingException, msg));
+    }
+
+    env.spawn_future(async move {
+        let mut data = queryer::query(sql)
+            .await
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        let result = match output.as_str() {
+            "csv" => data.to_csv().map(Either::A),
+            "json" => data.to_json().map(Either::A),
+            _ => data.to_arrow_ipc().map(|v| Either::B(Buffer::from(v))),
+        };
+        result.map_err(|e| Error::from_reason(e.to_string()))
+    })
+}
@@ -0,0 +1,88 @@
+use polars::export::arrow::{datatypes::Field, ffi};
+use polars::prelude::{DataFrame, Series};
+use pyo3::{exceptions, prelude::*, types::PyList};
+
+// Hand a DataFrame over to pyarrow through the Arrow C data interface, column by column
+pub fn to_pyarrow(py: Python, df: &DataFrame) -> PyResult<PyObject> {
+    let pyarrow = py.import("pyarrow")?;
+    let mut arrays = Vec::with_capacity(df.width());
+    for series in df.get_columns() {
+        arrays.push(series_to_pyarrow(pyarrow, series)?);
+    }
+
+    let names = PyList::new(py, df.get_column_names());
+    let table = pyarrow
+        .getattr("Table")?
+        .call_method1("from_arrays", (arrays, names))?;
+    Ok(table.to_object(py))
+}
+
+fn series_to_pyarrow(pyarrow: &PyModule, series: &Series) -> PyResult<PyObject> {
+    // a single chunk exports as a single arrow array
+    let series = series.rechunk();
+    let array = series.to_arrow(0);
+
+    let schema = Box::new(ffi::export_field_to_c(&Field::new(
+        series.name(),
+        array.data_type().clone(),
+        true,
+    )));
+    let array = Box::new(ffi::export_array_to_c(array));
+
+    let schema_ptr: *const ffi::ArrowSchema = &*schema;
+    let array_ptr: *const ffi::ArrowArray = &*array;
+
+    // pyarrow takes ownership of the buffers through the release callbacks
+    let array = pyarrow.getattr("Array")?.call_method1(
+        "_import_from_c",
+        (array_ptr as usize, schema_ptr as usize),
+    )?;
+    Ok(array.to_object(pyarrow.py()))
+}
+
+// Accept a pyarrow Table, a polars DataFrame or a pandas DataFrame
+pub fn from_python(py: Python, df: &PyAny) -> PyResult<DataFrame> {
+    let pyarrow = py.import("pyarrow")?;
+    let table_type = pyarrow.getattr("Table")?;
+    let table = if df.is_instance(table_type)? {
+        df
+    } else if df.hasattr("to_arrow")? {
+        df.call_method0("to_arrow")?
+    } else if df.hasattr("iloc")? {
+        table_type.call_method1("from_pandas", (df,))?
+    } else {
+        return Err(exceptions::PyTypeError::new_err(format!(
+            "Cannot register {} as a data source",
+            df.get_type().name()?
+        )));
+    };
+
+    let names: Vec<String> = table.getattr("column_names")?.extract()?;
+    let mut columns = Vec::with_capacity(names.len());
+    for (i, name) in names.iter().enumerate() {
+        let chunked = table.call_method1("column", (i,))?;
+        columns.push(series_from_pyarrow(name, chunked.call_method0("combine_chunks")?)?);
+    }
+
+    DataFrame::new(columns).map_err(|e| exceptions::PyValueError::new_err(e.to_string()))
+}
+
+fn series_from_pyarrow(name: &str, array: &PyAny) -> PyResult<Series> {
+    let array_ffi = Box::new(ffi::ArrowArray::empty());
+    let schema_ffi = Box::new(ffi::ArrowSchema::empty());
+
+    let array_ptr = &*array_ffi as *const ffi::ArrowArray;
+    let schema_ptr = &*schema_ffi as *const ffi::ArrowSchema;
+
+    // pyarrow moves the array into the structs we allocated
+    array.call_method1("_export_to_c", (array_ptr as usize, schema_ptr as usize))?;
+
+    let array = unsafe {
+        let field = ffi::import_field_from_c(&schema_ffi)
+            .map_err(|e| exceptions::PyValueError::new_err(e.to_string()))?;
+        ffi::import_array_from_c(*array_ffi, field.data_type)
+            .map_err(|e| exceptions::PyValueError::new_err(e.to_string()))?
+    };
+
+    Series::try_from((name, array)).map_err(|e| exceptions::PyValueError::new_err(e.to_string()))
+}
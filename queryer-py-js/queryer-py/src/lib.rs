@@ -1,4 +1,15 @@
-use pyo3::{exceptions, prelude::*};
+mod arrow;
+
+use pyo3::{
+    exceptions,
+    prelude::*,
+    sync::GILOnceCell,
+    types::{PyBool, PyDict, PyList, PyTuple},
+};
+use tokio::runtime::Runtime;
+
+// Built on the first query and shared by every later one
+static RUNTIME: GILOnceCell<Runtime> = GILOnceCell::new();
 
 #[pyfunction]
 pub fn example_sql() -> PyResult<String> {
@@ -6,11 +17,63 @@ pub fn example_sql() -> PyResult<String> {
 }
 
 #[pyfunction]
-pub fn query(sql: &str, output: Option<&str>) -> PyResult<String> {
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    let mut data = rt.block_on(async { queryer::query(sql).await.unwrap() });
+pub fn query(py: Python, sql: &str, output: Option<&str>) -> PyResult<PyObject> {
+    check_output(output)?;
+    let rt = runtime(py)?;
+    let data = py
+        .allow_threads(|| rt.block_on(async { queryer::query(sql).await }))
+        .map_err(|e| exceptions::PyRuntimeError::new_err(e.to_string()))?;
+    to_output(py, data, output)
+}
+
+#[pyfunction]
+pub fn query_with_params(
+    py: Python,
+    sql: &str,
+    params: &PyAny,
+    output: Option<&str>,
+) -> PyResult<PyObject> {
+    check_output(output)?;
+    let params = to_params(params)?;
+    let rt = runtime(py)?;
+    let data = py
+        .allow_threads(|| rt.block_on(async { queryer::query_with_params(sql, params).await }))
+        .map_err(|e| exceptions::PyRuntimeError::new_err(e.to_string()))?;
+    to_output(py, data, output)
+}
+
+fn runtime(py: Python) -> PyResult<&'static Runtime> {
+    RUNTIME.get_or_try_init(py, || {
+        Runtime::new().map_err(|e| exceptions::PyRuntimeError::new_err(e.to_string()))
+    })
+}
+
+// Like queryer-js, an unsupported output fails before the query runs
+fn check_output(output: Option<&str>) -> PyResult<()> {
+    match output {
+        Some("csv" | "arrow" | "polars" | "pandas") | None => Ok(()),
+        Some(v) => Err(exceptions::PyTypeError::new_err(format!(
+            "Output type {} not supported",
+            v
+        ))),
+    }
+}
+
+fn to_output(py: Python, mut data: queryer::DataSet, output: Option<&str>) -> PyResult<PyObject> {
     match output {
-        Some("csv") | None => Ok(data.to_csv().unwrap()),
+        Some("csv") | None => match data.to_csv() {
+            Ok(csv) => Ok(csv.into_py(py)),
+            Err(e) => Err(exceptions::PyRuntimeError::new_err(e.to_string())),
+        },
+        Some("arrow") => arrow::to_pyarrow(py, &data),
+        Some("polars") => {
+            let table = arrow::to_pyarrow(py, &data)?;
+            Ok(py.import("polars")?.call_method1("from_arrow", (table,))?.into())
+        }
+        Some("pandas") => {
+            let table = arrow::to_pyarrow(py, &data)?;
+            Ok(table.call_method0(py, "to_pandas")?)
+        }
         Some(v) => Err(exceptions::PyTypeError::new_err(format!(
             "Output type {} not supported",
             v
@@ -18,9 +81,68 @@ pub fn query(sql: &str, output: Option<&str>) -> PyResult<String> {
     }
 }
 
+// A list/tuple binds `$1` / `?` placeholders, a dict binds `:name` placeholders
+fn to_params(params: &PyAny) -> PyResult<queryer::Params> {
+    if let Ok(dict) = params.downcast::<PyDict>() {
+        let mut result = queryer::Params::new();
+        for (k, v) in dict {
+            result = result.bind(k.extract::<String>()?, to_param(v)?);
+        }
+        return Ok(result);
+    }
+
+    // any other iterable, a str included, would silently bind its items
+    if !params.is_instance_of::<PyList>() && !params.is_instance_of::<PyTuple>() {
+        return Err(exceptions::PyTypeError::new_err(format!(
+            "Parameters must be a list, tuple or dict, got {}",
+            params.get_type().name()?
+        )));
+    }
+
+    let mut result = queryer::Params::new();
+    for v in params.iter()? {
+        result = result.push(to_param(v?)?);
+    }
+    Ok(result)
+}
+
+fn to_param(v: &PyAny) -> PyResult<queryer::Param> {
+    if v.is_none() {
+        Ok(queryer::Param::Null)
+    } else if let Ok(v) = v.downcast::<PyBool>() {
+        // checked before int, since python bools are ints
+        Ok(queryer::Param::Bool(v.is_true()))
+    } else if let Ok(v) = v.extract::<i64>() {
+        Ok(queryer::Param::Int(v))
+    } else if let Ok(v) = v.extract::<f64>() {
+        Ok(queryer::Param::Float(v))
+    } else if let Ok(v) = v.extract::<String>() {
+        Ok(queryer::Param::Str(v))
+    } else {
+        Err(exceptions::PyTypeError::new_err(format!(
+            "Parameter type {} not supported",
+            v.get_type().name()?
+        )))
+    }
+}
+
+#[pyfunction]
+pub fn register(py: Python, name: &str, df: &PyAny) -> PyResult<()> {
+    queryer::register(name, arrow::from_python(py, df)?);
+    Ok(())
+}
+
+#[pyfunction]
+pub fn deregister(name: &str) -> PyResult<bool> {
+    Ok(queryer::deregister(name).is_some())
+}
+
 #[pymodule]
 fn queryer_py(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(query, m)?)?;
+    m.add_function(wrap_pyfunction!(query_with_params, m)?)?;
     m.add_function(wrap_pyfunction!(example_sql, m)?)?;
+    m.add_function(wrap_pyfunction!(register, m)?)?;
+    m.add_function(wrap_pyfunction!(deregister, m)?)?;
     Ok(())
 }
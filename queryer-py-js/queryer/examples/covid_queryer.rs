@@ -16,6 +16,7 @@ async fn main() -> Result<()> {
     );
     let df1 = query(sql).await?;
     println!("{:?}", df1);
+    println!("{:?}", df1.stats());
 
     Ok(())
 }
\ No newline at end of file
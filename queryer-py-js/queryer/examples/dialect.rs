@@ -11,6 +11,6 @@ fn main() {
         LIMIT 50 OFFSET 10 \
     ";
 
-    let ast = Parser::parse_sql(&GenericDialect::default(), sql);
+    let ast = Parser::parse_sql(&GenericDialect, sql);
     println!("{:#?}", ast);
 }
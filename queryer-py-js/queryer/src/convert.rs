@@ -96,7 +96,7 @@ impl TryFrom<Expression> for Expr {
             }),
             SqlExpr::Identifier(id) => Ok(Self::Column(Arc::from(id.value.as_ref()))),
             SqlExpr::Value(v) => Ok(Self::Literal(Value(v).try_into()?)),
-            v => Err(anyhow!("expr {:#?} is not supported", v)),
+            v => Err(anyhow!("expr {} is not supported", v)),
         }
     }
 }
@@ -137,7 +137,8 @@ impl<'a> TryFrom<Projection<'a>> for Expr {
                 Box::new(Expr::Column(Arc::from(id.to_string().as_ref()))),
                 Arc::from(alias.to_string().as_ref()),
             )),
-            SelectItem::QualifiedWildcard(v, _) => Ok(col(&v.to_string())),
+            // there is a single source, so whatever qualifies the wildcard names it
+            SelectItem::QualifiedWildcard(_, _) => Ok(col("*")),
             SelectItem::Wildcard(_) => Ok(col("*")),
             item => Err(anyhow!("projection {} not supported", item)),
         }
@@ -207,7 +208,12 @@ impl TryFrom<Value> for LiteralValue {
     type Error = anyhow::Error;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v.0 {
-            SqlValue::Number(v, _) => Ok(LiteralValue::Float64(v.parse().unwrap())),
+            // integers stay integers, so they compare and compute like the columns they meet
+            SqlValue::Number(v, _) => match v.parse() {
+                Ok(v) => Ok(LiteralValue::Int64(v)),
+                Err(_) => Ok(LiteralValue::Float64(v.parse()?)),
+            },
+            SqlValue::SingleQuotedString(v) => Ok(LiteralValue::Utf8(v)),
             SqlValue::Boolean(v) => Ok(LiteralValue::Boolean(v)),
             SqlValue::Null => Ok(LiteralValue::Null),
             v => Err(anyhow!("Value {} is not supported", v)),
@@ -229,7 +235,7 @@ mod tests {
             "select a, b, c from {} where a=1 order by c desc limit 5 offset 10",
             url
         );
-        let statement = &Parser::parse_sql(&TyrDialect::default(), sql.as_ref()).unwrap()[0];
+        let statement = &Parser::parse_sql(&TyrDialect, sql.as_ref()).unwrap()[0];
         let sql: Sql = statement.try_into().unwrap();
         assert_eq!(sql.source, url);
         assert_eq!(sql.limit, Some(5));
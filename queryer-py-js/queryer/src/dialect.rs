@@ -5,14 +5,11 @@ pub struct TyrDialect;
 
 impl Dialect for TyrDialect {
     fn is_identifier_start(&self, ch: char) -> bool {
-        ('a' ..= 'z').contains(&ch) || ('A' ..= 'Z').contains(&ch) || ch == '_'
+        ch.is_ascii_alphabetic() || ch == '_'
     }
 
     fn is_identifier_part(&self, ch: char) -> bool {
-        ('a' ..= 'z').contains(&ch)
-            || ('A' ..= 'Z').contains(&ch)
-            || ('0' ..= '9').contains(&ch)
-            || [':', '/', '?', '&', '=', '-', '_', '.'].contains(&ch)
+        ch.is_ascii_alphanumeric() || [':', '/', '?', '&', '=', '-', '_', '.'].contains(&ch)
     }
 }
 
@@ -37,6 +34,6 @@ mod tests {
 
     #[test]
     fn it_works() {
-        assert!(Parser::parse_sql(&TyrDialect::default(), &example_sql()).is_ok());
+        assert!(Parser::parse_sql(&TyrDialect, &example_sql()).is_ok());
     }
 }
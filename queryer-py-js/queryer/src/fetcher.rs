@@ -10,10 +10,12 @@ pub trait Fetch {
 
 pub async fn retrieve_data(source: impl AsRef<str>) -> Result<String> {
     let name = source.as_ref();
-    match &name[..4] {
-        "http" => UrlFetcher(name).fetch().await,
-        "file" => FileFetcher(name).fetch().await,
-        _ => return Err(anyhow!("We only support http/https/file at the moment")),
+    if name.starts_with("http") {
+        UrlFetcher(name).fetch().await
+    } else if name.starts_with("file") {
+        FileFetcher(name).fetch().await
+    } else {
+        Err(anyhow!("We only support http/https/file at the moment"))
     }
 }
 
@@ -2,13 +2,17 @@ mod convert;
 mod dialect;
 mod fetcher;
 mod loader;
+mod params;
+mod registry;
+mod stats;
 
 use anyhow::{anyhow, Result};
 use polars::chunked_array::ops::SortOptions;
-use polars::prelude::{CsvWriter, DataFrame, SerWriter, IntoLazy};
-use sqlparser::parser::Parser;
+use polars::prelude::{CsvWriter, DataFrame, IntoLazy, IpcWriter, JsonFormat, JsonWriter, SerWriter};
+use sqlparser::{ast::Statement, parser::Parser};
 use std::ops::{Deref, DerefMut};
-use tracing::info;
+use std::time::{Duration, Instant};
+use tracing::{field::Empty, info, info_span, Instrument};
 
 use convert::Sql;
 use fetcher::retrieve_data;
@@ -16,9 +20,12 @@ use loader::detect_content;
 
 pub use dialect::example_sql;
 pub use dialect::TyrDialect;
+pub use params::{Param, Params};
+pub use registry::{deregister, register};
+pub use stats::QueryStats;
 
 #[derive(Debug)]
-pub struct DataSet(DataFrame);
+pub struct DataSet(DataFrame, QueryStats);
 
 impl Deref for DataSet {
     type Target = DataFrame;
@@ -34,18 +41,58 @@ impl DerefMut for DataSet {
     }
 }
 
+impl From<DataFrame> for DataSet {
+    fn from(df: DataFrame) -> Self {
+        Self(df, QueryStats::default())
+    }
+}
+
 impl DataSet {
+    pub fn stats(&self) -> &QueryStats {
+        &self.1
+    }
+
+    pub fn into_inner(self) -> DataFrame {
+        self.0
+    }
+
     pub fn to_csv(&mut self) -> Result<String> {
         let mut buf = Vec::new();
         let mut writer = CsvWriter::new(&mut buf);
         writer.finish(self)?;
         Ok(String::from_utf8(buf)?)
     }
+
+    pub fn to_json(&mut self) -> Result<String> {
+        let mut buf = Vec::new();
+        let mut writer = JsonWriter::new(&mut buf).with_json_format(JsonFormat::Json);
+        writer.finish(self)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    pub fn to_arrow_ipc(&mut self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        let mut writer = IpcWriter::new(&mut buf);
+        writer.finish(self)?;
+        Ok(buf)
+    }
 }
 
 pub async fn query<T: AsRef<str>>(sql: T) -> Result<DataSet> {
-    let ast = Parser::parse_sql(&TyrDialect::default(), sql.as_ref())?;
+    let start = Instant::now();
+    let ast = info_span!("parse")
+        .in_scope(|| Parser::parse_sql(&TyrDialect, sql.as_ref()))?;
+    execute(ast, start.elapsed()).await
+}
 
+pub async fn query_with_params<T: AsRef<str>>(sql: T, params: impl Into<Params>) -> Result<DataSet> {
+    let start = Instant::now();
+    let ast = info_span!("parse")
+        .in_scope(|| params::bind(&TyrDialect, sql.as_ref(), &params.into()))?;
+    execute(ast, start.elapsed()).await
+}
+
+async fn execute(ast: Vec<Statement>, parse_duration: Duration) -> Result<DataSet> {
     if ast.len() != 1 {
         return Err(anyhow!("Only support single sql at the moment"));
     }
@@ -61,23 +108,53 @@ pub async fn query<T: AsRef<str>>(sql: T) -> Result<DataSet> {
         order_by,
     } = sql.try_into()?;
 
-    info!("retrieving data from source: {}", source);
+    let mut stats = QueryStats {
+        parse_duration,
+        ..Default::default()
+    };
 
-    let ds = detect_content(retrieve_data(source).await?).load()?;
+    let df = match registry::lookup(source) {
+        Some(df) => {
+            info!("using registered source: {}", source);
+            df
+        }
+        None => {
+            info!("retrieving data from source: {}", source);
+            let start = Instant::now();
+            let data = retrieve_data(source)
+                .instrument(info_span!("fetch", source))
+                .await?;
+            stats.fetch_duration = start.elapsed();
+            stats.bytes_fetched = data.len();
+
+            let start = Instant::now();
+            let ds = info_span!("load", bytes = data.len())
+                .in_scope(|| detect_content(data).load())?;
+            stats.load_duration = start.elapsed();
+            ds.0
+        }
+    };
+
+    let start = Instant::now();
+    let span = info_span!("execute", rows_scanned = df.height(), rows_returned = Empty);
+    let _guard = span.enter();
+    stats.rows_scanned = df.height();
 
     let mut filtered = match condition {
-        Some(expr) => ds.0.lazy().filter(expr),
-        None => ds.0.lazy(),
+        Some(expr) => df.lazy().filter(expr),
+        None => df.lazy(),
     };
 
+    // stable sorts from the last key to the first give the lexicographic order
     filtered = order_by
         .into_iter()
+        .rev()
         .fold(filtered, |acc, (col, desc)| {
             let sort_option = SortOptions {
                 descending: desc,
                 nulls_last: false,
                 multithreaded: false,
-                maintain_order: false,
+                maintain_order: true,
             };
             acc.sort(&col, sort_option)
         });
@@ -86,5 +163,10 @@ pub async fn query<T: AsRef<str>>(sql: T) -> Result<DataSet> {
         filtered = filtered.slice(offset.unwrap_or(0), limit.unwrap_or(usize::MAX) as u32);
     }
 
-    Ok(DataSet(filtered.select(selection).collect()?))
+    let df = filtered.select(selection).collect()?;
+    stats.execute_duration = start.elapsed();
+    stats.rows_returned = df.height();
+    span.record("rows_returned", df.height());
+
+    Ok(DataSet(df, stats))
 }
@@ -12,21 +12,29 @@ pub trait Load {
 #[non_exhaustive]
 pub enum Loader {
     Csv(CsvLoader),
+    Json(JsonLoader),
 }
 
 #[derive(Default, Debug)]
 pub struct CsvLoader(pub(crate) String);
 
+#[derive(Default, Debug)]
+pub struct JsonLoader(pub(crate) String);
+
 impl Loader {
     pub fn load(self) -> Result<DataSet> {
         match self {
             Loader::Csv(csv) => csv.load(),
+            Loader::Json(json) => json.load(),
         }
     }
 }
 
 pub fn detect_content(data: String) -> Loader {
-    Loader::Csv(CsvLoader(data))
+    match data.trim_start().chars().next() {
+        Some('[') | Some('{') => Loader::Json(JsonLoader(data)),
+        _ => Loader::Csv(CsvLoader(data)),
+    }
 }
 
 impl Load for CsvLoader {
@@ -36,6 +44,23 @@ impl Load for CsvLoader {
         let df = CsvReader::new(Cursor::new(self.0))
             .infer_schema(Some(16))
             .finish()?;
-        Ok(DataSet(df))
+        Ok(DataSet::from(df))
+    }
+}
+
+impl Load for JsonLoader {
+    type Error = anyhow::Error;
+
+    fn load(self) -> Result<DataSet, Self::Error> {
+        // an array of records, or one record per line
+        let format = if self.0.trim_start().starts_with('[') {
+            JsonFormat::Json
+        } else {
+            JsonFormat::JsonLines
+        };
+        let df = JsonReader::new(Cursor::new(self.0))
+            .with_json_format(format)
+            .finish()?;
+        Ok(DataSet::from(df))
     }
 }
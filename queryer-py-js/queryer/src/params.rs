@@ -0,0 +1,249 @@
+use anyhow::{anyhow, Result};
+use sqlparser::{
+    ast::Statement,
+    dialect::Dialect,
+    keywords::Keyword,
+    parser::Parser,
+    tokenizer::{Token, Tokenizer},
+};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Param {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+// Values bound to `$1` / `?` (positional) and `:name` (named) placeholders
+#[derive(Debug, Default, Clone)]
+pub struct Params {
+    positional: Vec<Param>,
+    named: HashMap<String, Param>,
+}
+
+impl Params {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(mut self, value: impl Into<Param>) -> Self {
+        self.positional.push(value.into());
+        self
+    }
+
+    pub fn bind(mut self, name: impl Into<String>, value: impl Into<Param>) -> Self {
+        self.named.insert(name.into(), value.into());
+        self
+    }
+
+    fn get(&self, placeholder: &Placeholder) -> Result<&Param> {
+        let value = match placeholder {
+            Placeholder::Index(i) => self.positional.get(*i),
+            Placeholder::Name(name) => self.named.get(name),
+        };
+        value.ok_or_else(|| anyhow!("No value bound for placeholder {}", placeholder))
+    }
+}
+
+impl<T: Into<Param>> From<Vec<T>> for Params {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().fold(Params::new(), Params::push)
+    }
+}
+
+impl<T: Into<Param>> From<HashMap<String, T>> for Params {
+    fn from(values: HashMap<String, T>) -> Self {
+        values
+            .into_iter()
+            .fold(Params::new(), |acc, (k, v)| acc.bind(k, v))
+    }
+}
+
+impl From<bool> for Param {
+    fn from(v: bool) -> Self {
+        Param::Bool(v)
+    }
+}
+
+impl From<i32> for Param {
+    fn from(v: i32) -> Self {
+        Param::Int(v as i64)
+    }
+}
+
+impl From<i64> for Param {
+    fn from(v: i64) -> Self {
+        Param::Int(v)
+    }
+}
+
+impl From<f64> for Param {
+    fn from(v: f64) -> Self {
+        Param::Float(v)
+    }
+}
+
+impl From<&str> for Param {
+    fn from(v: &str) -> Self {
+        Param::Str(v.to_string())
+    }
+}
+
+impl From<String> for Param {
+    fn from(v: String) -> Self {
+        Param::Str(v)
+    }
+}
+
+impl<T: Into<Param>> From<Option<T>> for Param {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Param::Null, Into::into)
+    }
+}
+
+enum Placeholder {
+    Index(usize),
+    Name(String),
+}
+
+impl std::fmt::Display for Placeholder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Placeholder::Index(i) => write!(f, "${}", i + 1),
+            Placeholder::Name(name) => write!(f, ":{}", name),
+        }
+    }
+}
+
+// Replace placeholder tokens with literal tokens before parsing, so bound values
+// never go through the SQL text and can't change the shape of the query
+pub(crate) fn bind(dialect: &dyn Dialect, sql: &str, params: &Params) -> Result<Vec<Statement>> {
+    let tokens = Tokenizer::new(dialect, sql).tokenize()?;
+
+    let mut bound = Vec::with_capacity(tokens.len());
+    let mut next_index = 0;
+    let mut iter = tokens.into_iter().peekable();
+    while let Some(token) = iter.next() {
+        let placeholder = match &token {
+            Token::Placeholder(p) if p == "?" => {
+                next_index += 1;
+                Placeholder::Index(next_index - 1)
+            }
+            // `$1`, and `?1` as used by sqlite
+            Token::Placeholder(p) => match p[1..].parse::<usize>() {
+                Ok(n) if n > 0 => Placeholder::Index(n - 1),
+                _ => return Err(anyhow!("Invalid placeholder {}", p)),
+            },
+            Token::Colon => match iter.peek() {
+                Some(Token::Word(w)) if w.quote_style.is_none() => {
+                    let name = w.value.clone();
+                    iter.next();
+                    Placeholder::Name(name)
+                }
+                _ => {
+                    bound.push(token);
+                    continue;
+                }
+            },
+            _ => {
+                bound.push(token);
+                continue;
+            }
+        };
+
+        let value = params.get(&placeholder)?;
+        let token = if follows_from(&bound) {
+            // data source, e.g. `FROM $1`
+            match value {
+                Param::Str(source) => Token::make_word(source, None),
+                v => {
+                    return Err(anyhow!(
+                        "Source placeholder {} must be bound to a string, got {:?}",
+                        placeholder,
+                        v
+                    ))
+                }
+            }
+        } else {
+            literal(value)
+        };
+        bound.push(token);
+    }
+
+    Ok(Parser::new(dialect).with_tokens(bound).parse_statements()?)
+}
+
+fn follows_from(tokens: &[Token]) -> bool {
+    tokens
+        .iter()
+        .rev()
+        .find(|t| !matches!(t, Token::Whitespace(_)))
+        .is_some_and(|t| matches!(t, Token::Word(w) if w.keyword == Keyword::FROM))
+}
+
+fn literal(value: &Param) -> Token {
+    match value {
+        Param::Null => Token::make_keyword("NULL"),
+        Param::Bool(true) => Token::make_keyword("TRUE"),
+        Param::Bool(false) => Token::make_keyword("FALSE"),
+        Param::Int(v) => Token::Number(v.to_string(), false),
+        // `{:?}` keeps the fraction of whole floats, `2.0` rather than `2`, so they stay floats
+        Param::Float(v) => Token::Number(format!("{:?}", v), false),
+        Param::Str(v) => Token::SingleQuotedString(v.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{convert::Sql, dialect::TyrDialect};
+    use polars::lazy::dsl::{col, lit};
+
+    #[test]
+    fn bind_positional_works() {
+        let url = "http://abc.xyz/abc?a=1&b=2";
+        let params = Params::from(vec![Param::from(url), Param::from(5), Param::from("x'y")]);
+        for sql in [
+            "select a from $1 where a >= $2 and b = $3",
+            "select a from ? where a >= ? and b = ?",
+        ] {
+            let ast = bind(&TyrDialect, sql, &params).unwrap();
+            let sql: Sql = (&ast[0]).try_into().unwrap();
+            assert_eq!(sql.source, url);
+            assert_eq!(
+                sql.condition,
+                Some(col("a").gt_eq(lit(5i64)).and(col("b").eq(lit("x'y"))))
+            );
+        }
+    }
+
+    #[test]
+    fn bind_named_works() {
+        let url = "file:///tmp/data.csv";
+        let params = Params::new().bind("src", url).bind("flag", true);
+        let ast = bind(&TyrDialect, "select a from :src where c = :flag", &params).unwrap();
+        let sql: Sql = (&ast[0]).try_into().unwrap();
+        assert_eq!(sql.source, url);
+        assert_eq!(sql.condition, Some(col("c").eq(lit(true))));
+    }
+
+    #[test]
+    fn bind_keeps_ints_and_floats_apart() {
+        let params = Params::new().bind("i", 2).bind("f", 2.0);
+        let ast = bind(&TyrDialect, "select a from t where a = :i and b = :f", &params).unwrap();
+        let sql: Sql = (&ast[0]).try_into().unwrap();
+        assert_eq!(sql.condition, Some(col("a").eq(lit(2i64)).and(col("b").eq(lit(2.0)))));
+    }
+
+    #[test]
+    fn bind_rejects_missing_and_mistyped_values() {
+        let params = Params::new().bind("n", 1.5);
+        let dialect = TyrDialect;
+        assert!(bind(&dialect, "select a from :n", &params).is_err());
+        assert!(bind(&dialect, "select a from t where a = :m", &params).is_err());
+        assert!(bind(&dialect, "select a from t where a = $2", &params).is_err());
+    }
+}
@@ -0,0 +1,38 @@
+use polars::prelude::DataFrame;
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+// Named in-memory data sources, looked up before falling back to fetching the source
+static SOURCES: OnceLock<RwLock<HashMap<String, DataFrame>>> = OnceLock::new();
+
+fn sources() -> &'static RwLock<HashMap<String, DataFrame>> {
+    SOURCES.get_or_init(Default::default)
+}
+
+pub fn register(name: impl Into<String>, df: DataFrame) {
+    sources().write().unwrap().insert(name.into(), df);
+}
+
+pub fn deregister(name: impl AsRef<str>) -> Option<DataFrame> {
+    sources().write().unwrap().remove(name.as_ref())
+}
+
+pub(crate) fn lookup(name: impl AsRef<str>) -> Option<DataFrame> {
+    // DataFrame columns are reference counted, so the clone is cheap
+    sources().read().unwrap().get(name.as_ref()).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::*;
+
+    #[test]
+    fn register_and_lookup_works() {
+        let df = df!("a" => &[1, 2, 3]).unwrap();
+        register("registry_test", df.clone());
+        assert_eq!(lookup("registry_test"), Some(df));
+        assert!(deregister("registry_test").is_some());
+        assert_eq!(lookup("registry_test"), None);
+    }
+}
@@ -0,0 +1,21 @@
+use std::time::Duration;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct QueryStats {
+    // size of the raw source, 0 when the source was served from memory
+    pub bytes_fetched: usize,
+    // parsing the sql, binding parameters included
+    pub parse_duration: Duration,
+    pub fetch_duration: Duration,
+    // decoding the fetched data into a DataFrame
+    pub load_duration: Duration,
+    pub execute_duration: Duration,
+    pub rows_scanned: usize,
+    pub rows_returned: usize,
+}
+
+impl QueryStats {
+    pub fn total_duration(&self) -> Duration {
+        self.parse_duration + self.fetch_duration + self.load_duration + self.execute_duration
+    }
+}
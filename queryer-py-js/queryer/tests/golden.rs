@@ -0,0 +1,60 @@
+// Runs every `tests/golden/*.sql` through `queryer::query` and compares the csv output
+// (or `error: <message>` for failing queries) with the `.expected` file next to it.
+// Set `UPDATE_GOLDEN=1` to rewrite the expected files from the current output.
+use std::{fs, path::Path};
+
+const GOLDEN_DIR: &str = "tests/golden";
+
+async fn run(sql: &str) -> String {
+    match queryer::query(sql).await {
+        Ok(mut ds) => ds.to_csv().unwrap(),
+        Err(e) => format!("error: {}\n", e),
+    }
+}
+
+#[tokio::test]
+async fn golden_files_match() {
+    let update = std::env::var("UPDATE_GOLDEN").is_ok();
+
+    let mut cases: Vec<_> = fs::read_dir(GOLDEN_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sql"))
+        .collect();
+    cases.sort();
+    assert!(!cases.is_empty(), "no golden files found in {}", GOLDEN_DIR);
+
+    let mut failures = Vec::new();
+    for case in &cases {
+        let sql = fs::read_to_string(case).unwrap();
+        let actual = run(sql.trim()).await;
+        let expected_path = case.with_extension("expected");
+
+        if update {
+            fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+
+        let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+        if actual != expected {
+            failures.push(format!(
+                "{}\n--- expected\n{}--- actual\n{}",
+                name(case),
+                expected,
+                actual
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} golden files differ:\n\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n")
+    );
+}
+
+fn name(path: &Path) -> String {
+    path.file_stem().unwrap().to_string_lossy().into_owned()
+}
@@ -0,0 +1 @@
+SELECT count(a) FROM file://tests/fixtures/people.csv
@@ -0,0 +1 @@
+SELECT a FROM x JOIN y ON x.id = y.id
@@ -0,0 +1 @@
+SELECT a FROM x, y
@@ -0,0 +1 @@
+SELECT name FROM a UNION SELECT name FROM b
@@ -0,0 +1 @@
+SELECT a FROM file://tests/fixtures/people.csv ORDER BY a + 1
@@ -0,0 +1 @@
+SELECT a FROM ftp://example.com/data.csv
@@ -0,0 +1 @@
+SELECT a FROM (SELECT a FROM x)
@@ -0,0 +1 @@
+SELECT a FROM file://tests/fixtures/people.csv WHERE a = X'FF'
@@ -0,0 +1 @@
+SELECT id, amount FROM file://tests/fixtures/orders.json WHERE amount > 50 ORDER BY amount DESC
@@ -0,0 +1 @@
+SELECT name FROM file://tests/fixtures/people.csv ORDER BY id LIMIT 2 OFFSET 3
@@ -0,0 +1 @@
+SELECT name FROM file://tests/fixtures/people.csv ORDER BY id OFFSET 4
@@ -0,0 +1 @@
+SELECT city, name FROM file://tests/fixtures/people.csv ORDER BY city, age DESC
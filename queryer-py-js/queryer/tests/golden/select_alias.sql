@@ -0,0 +1 @@
+SELECT name AS person, city FROM file://tests/fixtures/people.csv LIMIT 2
@@ -0,0 +1 @@
+SELECT name, age FROM file://tests/fixtures/people.csv
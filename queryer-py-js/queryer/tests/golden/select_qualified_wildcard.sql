@@ -0,0 +1 @@
+SELECT t . * FROM file://tests/fixtures/people.csv
@@ -0,0 +1 @@
+SELECT * FROM file://tests/fixtures/people.csv
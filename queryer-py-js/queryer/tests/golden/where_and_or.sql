@@ -0,0 +1 @@
+SELECT name FROM file://tests/fixtures/people.csv WHERE age < 30 OR city = 'Paris' AND active = true
@@ -0,0 +1 @@
+SELECT name FROM file://tests/fixtures/people.csv WHERE id % 2 = 0 AND age / 2 + 1 > 14
@@ -0,0 +1 @@
+SELECT name, city FROM file://tests/fixtures/people.csv WHERE city != 'Paris'
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use axum::http::StatusCode;
+use image::{imageops::FilterType, RgbaImage};
+use lazy_static::lazy_static;
+use rusttype::Font;
+use thiserror::Error;
+use tracing::warn;
+
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Error)]
+pub enum AssetError {
+    #[error("invalid asset name {0:?}, use up to 64 letters, digits, '-' or '_'")]
+    InvalidName(String),
+    #[error("{0} is already registered")]
+    Exists(String),
+    #[error("cannot decode {0}: {1}")]
+    Decode(String, String),
+}
+
+impl AssetError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AssetError::InvalidName(_) => StatusCode::BAD_REQUEST,
+            AssetError::Exists(_) => StatusCode::CONFLICT,
+            AssetError::Decode(..) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        }
+    }
+}
+
+lazy_static! {
+    static ref DEFAULT_WATERMARK: Arc<RgbaImage> = {
+        let data = include_bytes!("../rust-logo.png");
+        let watermark = image::load_from_memory(data).unwrap();
+        Arc::new(watermark.resize_exact(64, 64, FilterType::Nearest).to_rgba8())
+    };
+    static ref WATERMARKS: RwLock<HashMap<String, Arc<RgbaImage>>> = RwLock::new(HashMap::new());
+    static ref FONTS: RwLock<HashMap<String, Arc<Font<'static>>>> = RwLock::new(HashMap::new());
+}
+
+// An empty name is the built-in rust logo
+pub fn watermark(name: &str) -> Option<Arc<RgbaImage>> {
+    if name.is_empty() {
+        return Some(DEFAULT_WATERMARK.clone());
+    }
+    WATERMARKS.read().unwrap().get(name).cloned()
+}
+
+// An empty name is the font registered as "default"
+pub fn font(name: &str) -> Option<Arc<Font<'static>>> {
+    let name = if name.is_empty() { "default" } else { name };
+    FONTS.read().unwrap().get(name).cloned()
+}
+
+// Names are never reused, so results cached for a name stay valid
+pub fn register_watermark(name: &str, data: &[u8]) -> Result<(), AssetError> {
+    check_name(name)?;
+    let img = image::load_from_memory(data)
+        .map_err(|e| AssetError::Decode(name.to_owned(), e.to_string()))?
+        .to_rgba8();
+    insert(&WATERMARKS, name, img)
+}
+
+pub fn register_font(name: &str, data: Vec<u8>) -> Result<(), AssetError> {
+    check_name(name)?;
+    let font = Font::try_from_vec(data)
+        .ok_or_else(|| AssetError::Decode(name.to_owned(), "not a truetype or opentype font".into()))?;
+    insert(&FONTS, name, font)
+}
+
+fn insert<T>(assets: &RwLock<HashMap<String, Arc<T>>>, name: &str, asset: T) -> Result<(), AssetError> {
+    let mut assets = assets.write().unwrap();
+    if assets.contains_key(name) {
+        return Err(AssetError::Exists(name.to_owned()));
+    }
+    assets.insert(name.to_owned(), Arc::new(asset));
+    Ok(())
+}
+
+fn check_name(name: &str) -> Result<(), AssetError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(AssetError::InvalidName(name.to_owned()));
+    }
+    Ok(())
+}
+
+// Register `<dir>/watermarks/*` and `<dir>/fonts/*` by file stem, returning how many were loaded
+pub fn load_dir(dir: impl AsRef<Path>) -> (usize, usize) {
+    let dir = dir.as_ref();
+    let watermarks = load_files(&dir.join("watermarks"), |name, data| register_watermark(name, &data));
+    let fonts = load_files(&dir.join("fonts"), register_font);
+    (watermarks, fonts)
+}
+
+fn load_files(dir: &Path, register: impl Fn(&str, Vec<u8>) -> Result<(), AssetError>) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut loaded = 0;
+    for path in entries.flatten().map(|e| e.path()) {
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let result = fs::read(&path)
+            .map_err(|e| AssetError::Decode(name.to_owned(), e.to_string()))
+            .and_then(|data| register(name, data));
+        match result {
+            Ok(()) => loaded += 1,
+            Err(e) => warn!("Skip asset {}: {}", path.display(), e),
+        }
+    }
+    loaded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registered_watermarks_could_be_found() {
+        let data = include_bytes!("../rust-logo.png");
+        register_watermark("assets-test-logo", data).unwrap();
+        assert!(watermark("assets-test-logo").is_some());
+        assert!(watermark("").is_some());
+        assert!(watermark("assets-test-missing").is_none());
+    }
+
+    #[test]
+    fn names_should_be_checked() {
+        let data = include_bytes!("../rust-logo.png");
+        assert!(matches!(register_watermark("../logo", data), Err(AssetError::InvalidName(_))));
+        register_watermark("assets-test-once", data).unwrap();
+        let err = register_watermark("assets-test-once", data).unwrap_err();
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn invalid_assets_should_be_rejected() {
+        let err = register_watermark("assets-test-garbage", b"not an image").unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert!(register_font("assets-test-garbage", b"not a font".to_vec()).is_err());
+    }
+}
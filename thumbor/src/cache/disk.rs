@@ -0,0 +1,383 @@
+use anyhow::Result;
+use bytes::Bytes;
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tokio::fs;
+use tracing::warn;
+
+// Content-addressed cache on disk: `objects/<sha256 of data>` holds the bytes and
+// `refs/<sha256 of key>` names the object, so identical sources are stored once.
+// A ref may carry the source's Last-Modified on a second line.
+// Objects are evicted in LRU order, together with their refs, once the total size of
+// both exceeds the capacity; the order survives restarts through the file modification times.
+// Only the index is locked, files are read and written outside of it.
+pub struct DiskCache {
+    dir: PathBuf,
+    capacity: u64,
+    index: Mutex<Index>,
+}
+
+struct Index {
+    objects: LruCache<String, Object>,
+    refs: HashMap<String, Ref>,
+    size: u64,
+}
+
+struct Object {
+    len: u64,
+    refs: HashSet<String>,
+}
+
+struct Ref {
+    object: String,
+    last_modified: Option<String>,
+    len: u64,
+}
+
+// A file the index no longer knows about, to be deleted once the lock is released
+enum Stale {
+    Object(String),
+    Ref(String),
+}
+
+impl DiskCache {
+    pub async fn open(dir: impl AsRef<Path>, capacity: u64) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(dir.join("objects")).await?;
+        fs::create_dir_all(dir.join("refs")).await?;
+
+        let mut existing = Vec::new();
+        for (name, meta) in list_files(&dir.join("objects")).await? {
+            let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            existing.push((modified, name, meta.len()));
+        }
+        // oldest first, so the most recently used object ends up at the front of the LRU
+        existing.sort();
+
+        let mut index = Index {
+            objects: LruCache::unbounded(),
+            refs: HashMap::new(),
+            size: 0,
+        };
+        for (_, name, len) in existing {
+            index.size += len;
+            index.objects.put(name, Object { len, refs: HashSet::new() });
+        }
+        for (name, meta) in list_files(&dir.join("refs")).await? {
+            let path = dir.join("refs").join(&name);
+            let content = fs::read_to_string(&path).await.unwrap_or_default();
+            let mut lines = content.lines();
+            let object = lines.next().unwrap_or_default().to_owned();
+            if !index.objects.contains(&object) {
+                // the object was evicted before the ref could be removed
+                remove(&path).await;
+                continue;
+            }
+            let last_modified = lines.next().map(String::from);
+            index.insert_ref(name, Ref { object, last_modified, len: meta.len() });
+        }
+        // no key leads to these any more
+        let orphans: Vec<String> = index
+            .objects
+            .iter()
+            .filter(|(_, object)| object.refs.is_empty())
+            .map(|(name, _)| name.clone())
+            .collect();
+        let mut stale = Vec::new();
+        for object in orphans {
+            stale.extend(index.remove_object(&object));
+        }
+
+        let cache = Self {
+            dir,
+            capacity,
+            index: Mutex::new(index),
+        };
+        stale.extend(cache.evict(&mut cache.index.lock().unwrap()));
+        cache.remove(stale).await;
+        Ok(cache)
+    }
+
+    pub async fn get(&self, key: &str) -> Option<(Bytes, Option<String>)> {
+        let (object, last_modified) = {
+            let mut index = self.index.lock().unwrap();
+            let entry = index.refs.get(&hex_digest(key.as_bytes()))?;
+            let found = (entry.object.clone(), entry.last_modified.clone());
+            index.objects.promote(&found.0);
+            found
+        };
+
+        let path = self.object_path(&object);
+        match fs::read(&path).await {
+            Ok(data) => {
+                touch(&path).await;
+                Some((Bytes::from(data), last_modified))
+            }
+            Err(e) => {
+                warn!("Failed to read cached object {}: {}", object, e);
+                let stale = self.index.lock().unwrap().remove_object(&object);
+                self.remove(stale).await;
+                None
+            }
+        }
+    }
+
+    pub async fn put(&self, key: &str, data: &Bytes, last_modified: Option<&str>) -> Result<()> {
+        let len = data.len() as u64;
+        if len > self.capacity {
+            return Ok(());
+        }
+
+        let object = hex_digest(data);
+        let known = self.index.lock().unwrap().objects.contains(&object);
+        if !known {
+            write_atomic(&self.object_path(&object), data).await?;
+        }
+        let content = match last_modified {
+            Some(v) => format!("{}\n{}", object, v),
+            None => object.clone(),
+        };
+        let name = hex_digest(key.as_bytes());
+        write_atomic(&self.ref_path(&name), content.as_bytes()).await?;
+
+        let stale = {
+            let mut index = self.index.lock().unwrap();
+            match (index.objects.contains(&object), known) {
+                // evicted while the ref was written, so the ref points at nothing
+                (false, true) => vec![Stale::Ref(name)],
+                (found, _) => {
+                    if !found {
+                        index.size += len;
+                        index.objects.put(object.clone(), Object { len, refs: HashSet::new() });
+                    }
+                    let entry = Ref {
+                        object,
+                        last_modified: last_modified.map(String::from),
+                        len: content.len() as u64,
+                    };
+                    let mut stale = index.insert_ref(name, entry);
+                    stale.extend(self.evict(&mut index));
+                    stale
+                }
+            }
+        };
+        self.remove(stale).await;
+        Ok(())
+    }
+
+    pub fn size(&self) -> u64 {
+        self.index.lock().unwrap().size
+    }
+
+    fn evict(&self, index: &mut Index) -> Vec<Stale> {
+        let mut stale = Vec::new();
+        while index.size > self.capacity {
+            let Some(object) = index.objects.peek_lru().map(|(object, _)| object.clone()) else {
+                break;
+            };
+            stale.extend(index.remove_object(&object));
+        }
+        stale
+    }
+
+    async fn remove(&self, stale: Vec<Stale>) {
+        for entry in stale {
+            let path = match entry {
+                Stale::Object(object) => self.object_path(&object),
+                Stale::Ref(name) => self.ref_path(&name),
+            };
+            remove(&path).await;
+        }
+    }
+
+    fn object_path(&self, object: &str) -> PathBuf {
+        self.dir.join("objects").join(object)
+    }
+
+    fn ref_path(&self, name: &str) -> PathBuf {
+        self.dir.join("refs").join(name)
+    }
+}
+
+impl Index {
+    // Adds or replaces a ref, returning the object it pointed to before if nothing else does
+    fn insert_ref(&mut self, name: String, entry: Ref) -> Vec<Stale> {
+        let mut stale = Vec::new();
+        if let Some(old) = self.refs.remove(&name) {
+            self.size -= old.len;
+            if let Some(object) = self.objects.peek_mut(&old.object) {
+                object.refs.remove(&name);
+                if object.refs.is_empty() && old.object != entry.object {
+                    stale.extend(self.remove_object(&old.object));
+                }
+            }
+        }
+        if let Some(object) = self.objects.peek_mut(&entry.object) {
+            object.refs.insert(name.clone());
+        }
+        self.size += entry.len;
+        self.refs.insert(name, entry);
+        stale
+    }
+
+    // Forgets an object and every ref pointing to it
+    fn remove_object(&mut self, object: &str) -> Vec<Stale> {
+        let Some(entry) = self.objects.pop(object) else {
+            return Vec::new();
+        };
+        self.size -= entry.len;
+        let mut stale = vec![Stale::Object(object.to_owned())];
+        for name in entry.refs {
+            if let Some(r) = self.refs.remove(&name) {
+                self.size -= r.len;
+            }
+            stale.push(Stale::Ref(name));
+        }
+        stale
+    }
+}
+
+fn hex_digest(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+// Regular files in a directory, deleting the temporary files a crash left behind
+async fn list_files(dir: &Path) -> Result<Vec<(String, Metadata)>> {
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let meta = entry.metadata().await?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !meta.is_file() {
+            continue;
+        }
+        if name.ends_with(".tmp") {
+            remove(&entry.path()).await;
+            continue;
+        }
+        files.push((name, meta));
+    }
+    Ok(files)
+}
+
+// Each write gets its own temporary file, concurrent puts of the same object included
+async fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let tmp = path.with_extension(format!("{}.tmp", NEXT.fetch_add(1, Ordering::Relaxed)));
+    fs::write(&tmp, data).await?;
+    fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+async fn touch(path: &Path) {
+    if let Ok(file) = fs::OpenOptions::new().append(true).open(path).await {
+        let _ = file.into_std().await.set_modified(SystemTime::now());
+    }
+}
+
+async fn remove(path: &Path) {
+    if let Err(e) = fs::remove_file(path).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to remove cached file {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a ref without Last-Modified is the object's hex digest
+    const REF: u64 = 64;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("thumbor-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn survives_reopen() {
+        let dir = temp_dir("reopen");
+        let cache = DiskCache::open(&dir, 1024).await.unwrap();
+        cache.put("a", &Bytes::from_static(b"hello"), None).await.unwrap();
+        drop(cache);
+
+        let cache = DiskCache::open(&dir, 1024).await.unwrap();
+        assert_eq!(cache.size(), 5 + REF);
+        assert_eq!(cache.get("a").await, Some((Bytes::from_static(b"hello"), None)));
+        assert_eq!(cache.get("b").await, None);
+    }
+
+    #[tokio::test]
+    async fn stores_identical_content_once() {
+        let dir = temp_dir("dedup");
+        let cache = DiskCache::open(&dir, 1024).await.unwrap();
+        cache.put("a", &Bytes::from_static(b"same"), None).await.unwrap();
+        cache.put("b", &Bytes::from_static(b"same"), None).await.unwrap();
+        assert_eq!(cache.size(), 4 + 2 * REF);
+        assert_eq!(cache.get("b").await, Some((Bytes::from_static(b"same"), None)));
+    }
+
+    #[tokio::test]
+    async fn keeps_last_modified() {
+        let dir = temp_dir("modified");
+        let cache = DiskCache::open(&dir, 1024).await.unwrap();
+        let modified = "Wed, 21 Oct 2015 07:28:00 GMT";
+        cache.put("a", &Bytes::from_static(b"data"), Some(modified)).await.unwrap();
+        let (data, last_modified) = cache.get("a").await.unwrap();
+        assert_eq!((&data[..], last_modified.as_deref()), (&b"data"[..], Some(modified)));
+    }
+
+    #[tokio::test]
+    async fn evicts_least_recently_used() {
+        let dir = temp_dir("evict");
+        let cache = DiskCache::open(&dir, 2 * (4 + REF)).await.unwrap();
+        cache.put("a", &Bytes::from_static(b"aaaa"), None).await.unwrap();
+        cache.put("b", &Bytes::from_static(b"bbbb"), None).await.unwrap();
+        assert!(cache.get("a").await.is_some());
+
+        cache.put("c", &Bytes::from_static(b"cccc"), None).await.unwrap();
+        assert_eq!(cache.size(), 2 * (4 + REF));
+        assert!(cache.get("b").await.is_none());
+        assert!(cache.get("a").await.is_some());
+        assert!(cache.get("c").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn refs_count_against_the_capacity() {
+        let dir = temp_dir("refs");
+        let cache = DiskCache::open(&dir, 4 + 2 * REF).await.unwrap();
+        cache.put("a", &Bytes::from_static(b"same"), None).await.unwrap();
+        cache.put("b", &Bytes::from_static(b"same"), None).await.unwrap();
+        assert_eq!(cache.size(), 4 + 2 * REF);
+
+        // a third ref to the same object overflows it, and the object goes with all its refs
+        cache.put("c", &Bytes::from_static(b"same"), None).await.unwrap();
+        assert_eq!(cache.size(), 0);
+        assert_eq!(std::fs::read_dir(dir.join("refs")).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn open_removes_leftovers() {
+        let dir = temp_dir("leftovers");
+        let cache = DiskCache::open(&dir, 1024).await.unwrap();
+        cache.put("a", &Bytes::from_static(b"data"), None).await.unwrap();
+        drop(cache);
+        std::fs::write(dir.join("objects").join("abc.0.tmp"), b"partial").unwrap();
+        std::fs::write(dir.join("refs").join("def.1.tmp"), b"partial").unwrap();
+        std::fs::write(dir.join("objects").join("orphan"), b"nobody").unwrap();
+
+        let cache = DiskCache::open(&dir, 1024).await.unwrap();
+        assert_eq!(cache.size(), 4 + REF);
+        assert_eq!(std::fs::read_dir(dir.join("objects")).unwrap().count(), 1);
+        assert_eq!(std::fs::read_dir(dir.join("refs")).unwrap().count(), 1);
+        assert!(cache.get("a").await.is_some());
+    }
+}
@@ -0,0 +1,118 @@
+mod disk;
+pub use disk::DiskCache;
+
+use bytes::Bytes;
+use lru::LruCache;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+pub fn cache_key(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+// LRU cache bounded by the total size of its values instead of the number of entries
+pub struct SizedCache {
+    entries: LruCache<u64, Bytes>,
+    size: usize,
+    capacity: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl SizedCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: LruCache::unbounded(),
+            size: 0,
+            capacity,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &u64) -> Option<Bytes> {
+        match self.entries.get(key) {
+            Some(v) => {
+                self.hits += 1;
+                Some(v.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn put(&mut self, key: u64, value: Bytes) {
+        // a value larger than the whole budget would only flush everything else
+        if value.len() > self.capacity {
+            return;
+        }
+
+        self.size += value.len();
+        if let Some(old) = self.entries.put(key, value) {
+            self.size -= old.len();
+        }
+
+        while self.size > self.capacity {
+            match self.entries.pop_lru() {
+                Some((_, v)) => self.size -= v.len(),
+                None => break,
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_by_size() {
+        let mut cache = SizedCache::new(10);
+        cache.put(1, Bytes::from_static(b"aaaa"));
+        cache.put(2, Bytes::from_static(b"bbbb"));
+        assert!(cache.get(&1).is_some());
+
+        // 2 is now the least recently used entry
+        cache.put(3, Bytes::from_static(b"cccc"));
+        assert_eq!(cache.size(), 8);
+        assert!(cache.get(&2).is_none());
+        assert!(cache.get(&1).is_some());
+        assert!(cache.get(&3).is_some());
+    }
+
+    #[test]
+    fn skips_oversized_values() {
+        let mut cache = SizedCache::new(4);
+        cache.put(1, Bytes::from_static(b"aaaaa"));
+        assert_eq!(cache.len(), 0);
+        assert_eq!(cache.size(), 0);
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let mut cache = SizedCache::new(10);
+        cache.put(1, Bytes::from_static(b"a"));
+        cache.get(&1);
+        cache.get(&2);
+        assert_eq!(cache.hit_ratio(), 0.5);
+    }
+}
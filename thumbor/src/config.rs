@@ -0,0 +1,427 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use serde::{de, Deserialize, Deserializer};
+
+use crate::engine::EngineKind;
+use crate::format::{parse_format, OutputDefaults, HAS_AVIF};
+use crate::source::SourcePolicy;
+
+// read when it exists and no other file is given
+const DEFAULT_CONFIG: &str = "thumbor.toml";
+
+/// Everything thumbor can be configured with. Command line flags win over environment
+/// variables, which win over the config file, which wins over the defaults here
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub listen: SocketAddr,
+    // urls are only checked for a signature when a secret is set
+    pub secret: Option<String>,
+    #[serde(deserialize_with = "parse")]
+    pub engine: EngineKind,
+    pub assets_dir: PathBuf,
+    pub storage_dir: PathBuf,
+    pub cache: CacheConfig,
+    pub limits: Limits,
+    pub output: OutputDefaults,
+    pub sources: Sources,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    // downloaded sources kept in memory, in entries
+    pub sources: usize,
+    // processed images kept in memory, in bytes
+    pub results: usize,
+    pub disk_dir: PathBuf,
+    pub disk_size: u64,
+    // seconds, sent in Cache-Control
+    pub max_age: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub max_source_size: usize,
+    pub max_upload_size: usize,
+    // seconds
+    pub fetch_timeout: u64,
+    // seconds for a whole image or info request
+    pub deadline: u64,
+    pub queue: usize,
+    // 0 runs one job per CPU
+    pub workers: usize,
+    // images an image set renders in one multipart response
+    pub max_set_images: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sources {
+    pub allowed_schemes: Vec<String>,
+    pub allowed_hosts: Vec<String>,
+    pub allow_private: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([127, 0, 0, 1], 3000)),
+            secret: None,
+            engine: EngineKind::default(),
+            assets_dir: "assets".into(),
+            storage_dir: "uploads".into(),
+            cache: CacheConfig::default(),
+            limits: Limits::default(),
+            output: OutputDefaults::default(),
+            sources: Sources::default(),
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            sources: 100,
+            results: 64 * 1024 * 1024,
+            disk_dir: "cache".into(),
+            disk_size: 1024 * 1024 * 1024,
+            max_age: 24 * 60 * 60,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        let policy = SourcePolicy::default();
+        Self {
+            max_source_size: policy.max_size,
+            max_upload_size: 20 * 1024 * 1024,
+            fetch_timeout: policy.timeout.as_secs(),
+            deadline: 30,
+            queue: 64,
+            workers: 0,
+            max_set_images: 12,
+        }
+    }
+}
+
+impl Default for Sources {
+    fn default() -> Self {
+        let policy = SourcePolicy::default();
+        Self {
+            allowed_schemes: policy.allowed_schemes,
+            allowed_hosts: policy.allowed_hosts,
+            allow_private: policy.allow_private,
+        }
+    }
+}
+
+impl Config {
+    // The config file, then the environment and command line on top of it
+    pub fn load(cli: &Cli) -> Result<Self> {
+        let mut config = match &cli.config {
+            Some(path) => Self::read(path)?,
+            None if Path::new(DEFAULT_CONFIG).exists() => Self::read(Path::new(DEFAULT_CONFIG))?,
+            None => Self::default(),
+        };
+        cli.overrides.apply(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        text.parse().with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let output = &self.output;
+        for (name, quality) in [
+            ("jpeg_quality", output.jpeg_quality),
+            ("webp_quality", output.webp_quality),
+            ("avif_quality", output.avif_quality),
+        ] {
+            if !(1..=100).contains(&quality) {
+                bail!("output.{} {} is not between 1 and 100", name, quality);
+            }
+        }
+
+        if output.format == crate::pb::output::Format::Avif && !HAS_AVIF {
+            bail!("output.format avif needs thumbor built with the avif feature");
+        }
+
+        let cache = &self.cache;
+        let limits = &self.limits;
+        for (name, value) in [
+            ("cache.sources", cache.sources as u64),
+            ("cache.results", cache.results as u64),
+            ("cache.disk_size", cache.disk_size),
+            ("limits.max_source_size", limits.max_source_size as u64),
+            ("limits.max_upload_size", limits.max_upload_size as u64),
+            ("limits.fetch_timeout", limits.fetch_timeout),
+            ("limits.deadline", limits.deadline),
+            ("limits.max_set_images", limits.max_set_images as u64),
+        ] {
+            if value == 0 {
+                bail!("{} must be greater than 0", name);
+            }
+        }
+        if self.sources.allowed_schemes.is_empty() {
+            bail!("sources.allowed_schemes must not be empty");
+        }
+        Ok(())
+    }
+
+    pub fn policy(&self) -> SourcePolicy {
+        SourcePolicy {
+            allowed_schemes: self.sources.allowed_schemes.clone(),
+            allowed_hosts: self.sources.allowed_hosts.clone(),
+            allow_private: self.sources.allow_private,
+            max_size: self.limits.max_source_size,
+            timeout: Duration::from_secs(self.limits.fetch_timeout),
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = toml::de::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s)
+    }
+}
+
+impl Limits {
+    pub fn deadline(&self) -> Duration {
+        Duration::from_secs(self.deadline)
+    }
+
+    pub fn workers(&self) -> usize {
+        match self.workers {
+            0 => std::thread::available_parallelism().map_or(4, |n| n.get()),
+            n => n,
+        }
+    }
+}
+
+fn parse<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "thumbor", about = "An image processing server")]
+pub struct Cli {
+    /// Config file, thumbor.toml is read when it exists
+    #[arg(short, long, env = "THUMBOR_CONFIG")]
+    pub config: Option<PathBuf>,
+    #[command(flatten)]
+    pub overrides: Overrides,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Print the signed path for a spec and a url
+    Sign { spec: String, url: String },
+}
+
+/// Settings that can be given as a flag or an environment variable, each overriding the config file
+#[derive(Debug, Default, clap::Args)]
+pub struct Overrides {
+    #[arg(long, env = "THUMBOR_LISTEN")]
+    pub listen: Option<SocketAddr>,
+    #[arg(long, env = "THUMBOR_SECRET", hide_env_values = true)]
+    pub secret: Option<String>,
+    /// photon or image
+    #[arg(long, env = "THUMBOR_ENGINE")]
+    pub engine: Option<EngineKind>,
+    #[arg(long, env = "THUMBOR_ASSETS_DIR")]
+    pub assets_dir: Option<PathBuf>,
+    #[arg(long, env = "THUMBOR_STORAGE_DIR")]
+    pub storage_dir: Option<PathBuf>,
+    /// Downloaded sources kept in memory
+    #[arg(long, env = "THUMBOR_SOURCE_CACHE")]
+    pub source_cache: Option<usize>,
+    /// Bytes of processed images kept in memory
+    #[arg(long, env = "THUMBOR_RESULT_CACHE")]
+    pub result_cache: Option<usize>,
+    #[arg(long, env = "THUMBOR_DISK_CACHE_DIR")]
+    pub disk_cache_dir: Option<PathBuf>,
+    /// Bytes of sources kept on disk
+    #[arg(long, env = "THUMBOR_DISK_CACHE_SIZE")]
+    pub disk_cache_size: Option<u64>,
+    /// Seconds sent in Cache-Control
+    #[arg(long, env = "THUMBOR_CACHE_MAX_AGE")]
+    pub cache_max_age: Option<u64>,
+    #[arg(long, env = "THUMBOR_MAX_SOURCE_SIZE")]
+    pub max_source_size: Option<usize>,
+    #[arg(long, env = "THUMBOR_MAX_UPLOAD_SIZE")]
+    pub max_upload_size: Option<usize>,
+    /// Seconds
+    #[arg(long, env = "THUMBOR_FETCH_TIMEOUT")]
+    pub fetch_timeout: Option<u64>,
+    /// Seconds for a whole image or info request
+    #[arg(long, env = "THUMBOR_DEADLINE")]
+    pub deadline: Option<u64>,
+    #[arg(long, env = "THUMBOR_QUEUE")]
+    pub queue: Option<usize>,
+    /// 0 runs one job per CPU
+    #[arg(long, env = "THUMBOR_WORKERS")]
+    pub workers: Option<usize>,
+    /// Images an image set renders in one multipart response
+    #[arg(long, env = "THUMBOR_MAX_SET_IMAGES")]
+    pub max_set_images: Option<usize>,
+    /// auto, jpeg, png, webp, avif or gif
+    #[arg(long, env = "THUMBOR_FORMAT", value_parser = parse_format)]
+    pub format: Option<crate::pb::output::Format>,
+    #[arg(long, env = "THUMBOR_JPEG_QUALITY")]
+    pub jpeg_quality: Option<u8>,
+    #[arg(long, env = "THUMBOR_WEBP_QUALITY")]
+    pub webp_quality: Option<u8>,
+    #[arg(long, env = "THUMBOR_AVIF_QUALITY")]
+    pub avif_quality: Option<u8>,
+    /// Comma separated, replaces the configured hosts
+    #[arg(long, env = "THUMBOR_ALLOWED_HOSTS", value_delimiter = ',')]
+    pub allowed_hosts: Option<Vec<String>>,
+    #[arg(long, env = "THUMBOR_ALLOW_PRIVATE")]
+    pub allow_private: Option<bool>,
+}
+
+impl Overrides {
+    pub fn apply(&self, config: &mut Config) {
+        fn set<T: Clone>(value: &Option<T>, target: &mut T) {
+            if let Some(value) = value {
+                *target = value.clone();
+            }
+        }
+
+        set(&self.listen, &mut config.listen);
+        if self.secret.is_some() {
+            config.secret = self.secret.clone();
+        }
+        set(&self.engine, &mut config.engine);
+        set(&self.assets_dir, &mut config.assets_dir);
+        set(&self.storage_dir, &mut config.storage_dir);
+
+        let cache = &mut config.cache;
+        set(&self.source_cache, &mut cache.sources);
+        set(&self.result_cache, &mut cache.results);
+        set(&self.disk_cache_dir, &mut cache.disk_dir);
+        set(&self.disk_cache_size, &mut cache.disk_size);
+        set(&self.cache_max_age, &mut cache.max_age);
+
+        let limits = &mut config.limits;
+        set(&self.max_source_size, &mut limits.max_source_size);
+        set(&self.max_upload_size, &mut limits.max_upload_size);
+        set(&self.fetch_timeout, &mut limits.fetch_timeout);
+        set(&self.deadline, &mut limits.deadline);
+        set(&self.queue, &mut limits.queue);
+        set(&self.workers, &mut limits.workers);
+        set(&self.max_set_images, &mut limits.max_set_images);
+
+        let output = &mut config.output;
+        set(&self.format, &mut output.format);
+        set(&self.jpeg_quality, &mut output.jpeg_quality);
+        set(&self.webp_quality, &mut output.webp_quality);
+        set(&self.avif_quality, &mut output.avif_quality);
+
+        set(&self.allowed_hosts, &mut config.sources.allowed_hosts);
+        set(&self.allow_private, &mut config.sources.allow_private);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::output;
+
+    const SAMPLE: &str = r#"
+listen = "0.0.0.0:8080"
+secret = "s3cret"
+engine = "image"
+
+[cache]
+results = 1024
+max_age = 60
+
+[limits]
+deadline = 5
+workers = 2
+
+[output]
+format = "webp"
+webp_quality = 60
+
+[sources]
+allowed_hosts = ["*.example.com"]
+"#;
+
+    #[test]
+    fn file_should_override_defaults() {
+        let config: Config = SAMPLE.parse().unwrap();
+        assert_eq!(config.listen, "0.0.0.0:8080".parse().unwrap());
+        assert_eq!(config.secret.as_deref(), Some("s3cret"));
+        assert_eq!(config.engine, EngineKind::ImageRs);
+        assert_eq!(config.cache.results, 1024);
+        assert_eq!(config.cache.sources, 100);
+        assert_eq!(config.limits.deadline(), Duration::from_secs(5));
+        assert_eq!(config.limits.workers(), 2);
+        assert_eq!(config.output.format, output::Format::Webp);
+        assert_eq!(config.output.webp_quality, 60);
+        assert_eq!(config.output.jpeg_quality, 85);
+
+        let policy = config.policy();
+        assert_eq!(policy.allowed_hosts, vec!["*.example.com".to_string()]);
+        assert_eq!(policy.allowed_schemes, SourcePolicy::default().allowed_schemes);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn unknown_or_invalid_settings_should_be_rejected() {
+        assert!("listen_on = \"0.0.0.0:80\"".parse::<Config>().is_err());
+        assert!("[cache]\nsize = 1".parse::<Config>().is_err());
+        assert!("engine = \"magick\"".parse::<Config>().is_err());
+        assert!("[output]\nformat = \"bmp\"".parse::<Config>().is_err());
+
+        let config: Config = "[output]\njpeg_quality = 0".parse().unwrap();
+        assert!(config.validate().is_err());
+        let config: Config = "[limits]\ndeadline = 0".parse().unwrap();
+        assert!(config.validate().is_err());
+        let config: Config = "[limits]\nmax_set_images = 0".parse().unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn overrides_should_win_over_the_file() {
+        let mut config: Config = SAMPLE.parse().unwrap();
+        let overrides = Overrides {
+            secret: Some("other".into()),
+            engine: Some(EngineKind::Photon),
+            deadline: Some(10),
+            format: Some(output::Format::Auto),
+            allowed_hosts: Some(vec![]),
+            ..Default::default()
+        };
+        overrides.apply(&mut config);
+        assert_eq!(config.secret.as_deref(), Some("other"));
+        assert_eq!(config.engine, EngineKind::Photon);
+        assert_eq!(config.limits.deadline, 10);
+        assert_eq!(config.limits.workers, 2);
+        assert_eq!(config.output.format, output::Format::Auto);
+        assert!(config.sources.allowed_hosts.is_empty());
+        assert_eq!(config.listen, "0.0.0.0:8080".parse().unwrap());
+    }
+}
@@ -0,0 +1,192 @@
+// Decoding and encoding animated GIF and WebP, frame by frame
+use std::io::Cursor;
+
+use anyhow::{bail, Result};
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, Frame, ImageError, ImageFormat};
+
+use crate::error::ThumborError;
+use crate::format::OutputFormat;
+
+// decoded frames are kept in memory as RGBA, 100M pixels is 400 MiB
+const MAX_PIXELS: u64 = 100_000_000;
+
+// Whether the source has more than one frame, from its structure alone, without decoding it
+pub fn is_animated(data: &[u8]) -> bool {
+    match image::guess_format(data) {
+        Ok(ImageFormat::Gif) => gif_frames(data) > 1,
+        Ok(ImageFormat::WebP) => webp_animated(data),
+        _ => false,
+    }
+}
+
+// Every frame, composited onto the full canvas; None for sources with a single frame
+pub fn decode(data: &[u8]) -> Result<Option<Vec<Frame>>, ThumborError> {
+    if !is_animated(data) {
+        return Ok(None);
+    }
+    let invalid = |e: ImageError| ThumborError::Decode(e.to_string());
+    let frames = match image::guess_format(data).map_err(invalid)? {
+        ImageFormat::Gif => GifDecoder::new(Cursor::new(data)).map_err(invalid)?.into_frames(),
+        _ => WebPDecoder::new(Cursor::new(data)).map_err(invalid)?.into_frames(),
+    };
+
+    let mut pixels = 0;
+    let mut decoded = vec![];
+    for frame in frames {
+        let frame = frame.map_err(invalid)?;
+        pixels += frame.buffer().width() as u64 * frame.buffer().height() as u64;
+        if pixels > MAX_PIXELS {
+            return Err(ThumborError::TooManyPixels(MAX_PIXELS));
+        }
+        decoded.push(frame);
+    }
+    Ok(Some(decoded))
+}
+
+// Loops forever, as browsers do with most animations
+pub fn encode(frames: Vec<Frame>, format: OutputFormat) -> Result<Vec<u8>> {
+    match format {
+        OutputFormat::Gif => {
+            let mut buffer = Vec::with_capacity(32768);
+            let mut encoder = GifEncoder::new(&mut buffer);
+            encoder.set_repeat(Repeat::Infinite)?;
+            encoder.encode_frames(frames)?;
+            drop(encoder);
+            Ok(buffer)
+        }
+        OutputFormat::WebP(quality) => encode_webp(&frames, quality),
+        _ => bail!("{} cannot be animated", format.content_type()),
+    }
+}
+
+fn encode_webp(frames: &[Frame], quality: u8) -> Result<Vec<u8>> {
+    let Some(first) = frames.first() else {
+        bail!("animation has no frames");
+    };
+    let (width, height) = first.buffer().dimensions();
+    let mut config = webp::WebPConfig::new().map_err(|_| anyhow::anyhow!("failed to set up the webp encoder"))?;
+    config.quality = quality as f32;
+
+    let mut encoder = webp::AnimEncoder::new(width, height, &config);
+    encoder.set_loop_count(0);
+    let mut timestamp = 0;
+    for frame in frames {
+        if frame.buffer().dimensions() != (width, height) {
+            bail!("animation frames differ in size");
+        }
+        encoder.add_frame(webp::AnimFrame::from_rgba(frame.buffer(), width, height, timestamp));
+        timestamp += delay_ms(frame);
+    }
+    let data = encoder
+        .try_encode()
+        .map_err(|e| anyhow::anyhow!("failed to encode the animation: {:?}", e))?;
+    Ok(data.to_vec())
+}
+
+fn delay_ms(frame: &Frame) -> i32 {
+    let (numer, denom) = frame.delay().numer_denom_ms();
+    (numer / denom.max(1)) as i32
+}
+
+// Image descriptors in a GIF, stopping at the second; extension and image data are skipped
+// block by block, so this never decompresses anything
+fn gif_frames(data: &[u8]) -> usize {
+    // header and logical screen descriptor, then the global color table if there is one
+    let Some(&flags) = data.get(10) else {
+        return 0;
+    };
+    let mut at = 13 + color_table(flags);
+    let mut frames = 0;
+    while let Some(&block) = data.get(at) {
+        match block {
+            // image descriptor, local color table, LZW code size, then data sub-blocks
+            0x2c => {
+                frames += 1;
+                if frames > 1 {
+                    break;
+                }
+                let Some(&flags) = data.get(at + 9) else {
+                    break;
+                };
+                at = skip_sub_blocks(data, at + 10 + color_table(flags) + 1);
+            }
+            // extension: label, then data sub-blocks
+            0x21 => at = skip_sub_blocks(data, at + 2),
+            // trailer or garbage
+            _ => break,
+        }
+    }
+    frames
+}
+
+fn color_table(flags: u8) -> usize {
+    match flags & 0x80 {
+        0 => 0,
+        _ => 3 << ((flags & 0x07) + 1),
+    }
+}
+
+fn skip_sub_blocks(data: &[u8], mut at: usize) -> usize {
+    while let Some(&len) = data.get(at) {
+        at += 1 + len as usize;
+        if len == 0 {
+            break;
+        }
+    }
+    at
+}
+
+// The animation flag of a VP8X header
+fn webp_animated(data: &[u8]) -> bool {
+    data.get(12..16) == Some(b"VP8X") && data.get(20).is_some_and(|flags| flags & 0x02 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Delay, Rgba, RgbaImage};
+
+    fn gif(frames: &[[u8; 4]]) -> Vec<u8> {
+        let frames = frames.iter().map(|&color| {
+            let img = RgbaImage::from_pixel(8, 6, Rgba(color));
+            Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(100, 1))
+        });
+        let mut data = vec![];
+        GifEncoder::new(&mut data).encode_frames(frames).unwrap();
+        data
+    }
+
+    #[test]
+    fn animated_gifs_should_be_detected() {
+        let animated = gif(&[[255, 0, 0, 255], [0, 0, 255, 255], [0, 255, 0, 255]]);
+        assert_eq!(gif_frames(&animated), 2);
+        assert!(is_animated(&animated));
+        assert!(!is_animated(&gif(&[[255, 0, 0, 255]])));
+        assert!(!is_animated(include_bytes!("../../rust-logo.png")));
+        assert!(!is_animated(&animated[..20]));
+    }
+
+    #[test]
+    fn frames_should_round_trip_through_gif() {
+        let colors = [[255, 0, 0, 255], [0, 0, 255, 255], [0, 255, 0, 255]];
+        let frames = decode(&gif(&colors)).unwrap().unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(delay_ms(&frames[1]), 100);
+
+        let data = encode(frames, OutputFormat::Gif).unwrap();
+        let frames = decode(&data).unwrap().unwrap();
+        let pixels: Vec<_> = frames.iter().map(|f| f.buffer().get_pixel(4, 3).0).collect();
+        assert_eq!(pixels, colors);
+        assert!(decode(&gif(&[colors[0]])).unwrap().is_none());
+    }
+
+    #[test]
+    fn vp8x_animation_flag_should_be_read() {
+        let mut header = b"RIFF\0\0\0\0WEBPVP8X\x0a\0\0\0".to_vec();
+        assert!(!webp_animated(&header));
+        header.push(0x02);
+        assert!(webp_animated(&header));
+    }
+}
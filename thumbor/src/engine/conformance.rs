@@ -0,0 +1,250 @@
+// Every spec runs through both engines: they must agree on whether it succeeds and on the
+// output size, and on the pixels for transforms with the same (or shared) implementation
+use std::io::Cursor;
+
+use bytes::Bytes;
+use image::codecs::gif::GifEncoder;
+use image::{AnimationDecoder, Delay, DynamicImage, Frame, ImageOutputFormat, Rgba, RgbaImage};
+
+use super::{Engine, EngineKind, ImageRs, Photon};
+use crate::error::ThumborError;
+use crate::format::OutputFormat;
+use crate::pb::resize::{Fit, Gravity, ResizeType, SampleFilter};
+use crate::pb::{filter, spec, Anchor, Color, Contrast, Crop, Fliph, Flipv, Filter, Resize, Spec};
+
+// how far apart a channel may be; None compares the size only
+type Tolerance = Option<u8>;
+
+const EXACT: Tolerance = Some(0);
+const ROUNDING: Tolerance = Some(2);
+const SIZE_ONLY: Tolerance = None;
+
+// 48x32 gradient with a translucent corner, small enough for seam carving in debug builds
+fn sample() -> Bytes {
+    let img = RgbaImage::from_fn(48, 32, |x, y| {
+        let alpha = if x < 8 && y < 8 { 128 } else { 255 };
+        Rgba([(x * 5) as u8, (y * 7) as u8, ((x + y) * 3) as u8, alpha])
+    });
+    let mut buf = Cursor::new(vec![]);
+    DynamicImage::ImageRgba8(img)
+        .write_to(&mut buf, ImageOutputFormat::Png)
+        .unwrap();
+    buf.into_inner().into()
+}
+
+// three 24x16 frames of one color each
+fn animation() -> Bytes {
+    let frames = [[255, 0, 0, 255], [0, 0, 255, 255], [0, 255, 0, 255]].map(|color| {
+        let img = RgbaImage::from_pixel(24, 16, Rgba(color));
+        Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(80, 1))
+    });
+    let mut data = vec![];
+    GifEncoder::new(&mut data).encode_frames(frames).unwrap();
+    data.into()
+}
+
+fn render<E>(specs: &[Spec]) -> Result<RgbaImage, ThumborError>
+where
+    E: Engine + TryFrom<Bytes, Error = ThumborError>,
+{
+    let mut engine = E::try_from(sample())?;
+    engine.apply(specs)?;
+    let png = engine.generate(OutputFormat::Png)?;
+    Ok(image::load_from_memory(&png).unwrap().into_rgba8())
+}
+
+fn spec(data: spec::Data) -> Spec {
+    Spec { data: Some(data) }
+}
+
+fn crop(x1: u32, y1: u32, x2: u32, y2: u32) -> Spec {
+    spec(spec::Data::Crop(Crop { x1, y1, x2, y2 }))
+}
+
+fn contrast(contrast: f32) -> Spec {
+    spec(spec::Data::Contrast(Contrast { contrast }))
+}
+
+fn with_resize(mut spec: Spec, f: impl FnOnce(&mut Resize)) -> Spec {
+    if let Some(spec::Data::Resize(r)) = spec.data.as_mut() {
+        f(r);
+    }
+    spec
+}
+
+fn max_difference(a: &RgbaImage, b: &RgbaImage) -> u8 {
+    a.pixels()
+        .zip(b.pixels())
+        .flat_map(|(p, q)| (0..4).map(move |i| p[i].abs_diff(q[i])))
+        .max()
+        .unwrap_or(0)
+}
+
+fn passing() -> Vec<(Spec, Tolerance)> {
+    let red = Some(Color::new(255, 0, 0, 255));
+    vec![
+        (crop(4, 2, 40, 30), EXACT),
+        (spec(spec::Data::Flipv(Flipv {})), EXACT),
+        (spec(spec::Data::Fliph(Fliph {})), EXACT),
+        (contrast(60.0), ROUNDING),
+        (contrast(-60.0), ROUNDING),
+        (Spec::new_filter(filter::Filter::Oceanic), ROUNDING),
+        (Spec::new_filter(filter::Filter::Islands), ROUNDING),
+        (Spec::new_filter(filter::Filter::Marine), ROUNDING),
+        (Spec::new_resize(24, 16, SampleFilter::Nearest), EXACT),
+        (Spec::new_resize(96, 64, SampleFilter::Triangle), ROUNDING),
+        (Spec::new_resize(30, 0, SampleFilter::CatmullRom), ROUNDING),
+        (Spec::new_resize_fit(20, 20, Fit::Cover, Gravity::Entropy, None), ROUNDING),
+        (Spec::new_resize_fit(20, 20, Fit::Contain, Gravity::South, red.clone()), ROUNDING),
+        (Spec::new_resize_fit(20, 20, Fit::Inside, Gravity::Center, None), ROUNDING),
+        (Spec::new_resize_fit(20, 20, Fit::Outside, Gravity::Center, None), ROUNDING),
+        (Spec::new_resize_seam_carve(40, 24), SIZE_ONLY),
+        (Spec::new_watermark(4, 4), EXACT),
+        (Spec::new_tiled_watermark("", 2, 0.25, 0.5), EXACT),
+        (Spec::new_named_watermark("", Anchor::BottomRight, 2, 2, 0.5, 0.0), EXACT),
+        (Spec::new_rotate(90.0, None), EXACT),
+        (Spec::new_rotate(-180.0, None), EXACT),
+        (Spec::new_rotate(30.0, red.clone()), EXACT),
+        (Spec::new_blur(3), SIZE_ONLY),
+        (Spec::new_sharpen(1.5, 2), EXACT),
+        (Spec::new_grayscale(), ROUNDING),
+        (Spec::new_sepia(), ROUNDING),
+        (Spec::new_brightness(40), ROUNDING),
+        (Spec::new_brightness(-40), ROUNDING),
+        (Spec::new_brightness(0), EXACT),
+        (Spec::new_saturation(0.5), SIZE_ONLY),
+        (Spec::new_saturation(-0.5), SIZE_ONLY),
+        (Spec::new_hue(120.0), SIZE_ONLY),
+        (Spec::new_hue(360.0), EXACT),
+        (Spec::new_padding(1, 2, 3, 4, red), EXACT),
+        (Spec::new_round_corners(6), EXACT),
+    ]
+}
+
+fn failing() -> Vec<Spec> {
+    vec![
+        crop(0, 0, 49, 10),
+        crop(10, 0, 10, 10),
+        contrast(300.0),
+        spec(spec::Data::Filter(Filter { filter: 99 })),
+        Spec::new_resize(0, 0, SampleFilter::Nearest),
+        Spec::new_resize(9000, 10, SampleFilter::Nearest),
+        Spec::new_resize_seam_carve(96, 64),
+        with_resize(Spec::new_resize_fit(20, 20, Fit::Cover, Gravity::Center, None), |r| {
+            r.rtype = ResizeType::SeamCarve as i32
+        }),
+        with_resize(Spec::new_resize(10, 10, SampleFilter::Nearest), |r| r.fit = 99),
+        Spec::new_named_watermark("missing", Anchor::TopLeft, 0, 0, 0.0, 0.0),
+        Spec::new_named_watermark("", Anchor::TopLeft, 0, 0, 2.0, 0.0),
+        Spec::new_tiled_watermark("", u32::MAX, 0.25, 0.5),
+        Spec::new_text("hi", "missing", 12.0, None, Anchor::TopLeft, 0, 0),
+        Spec::new_rotate(f32::NAN, None),
+        Spec::new_blur(0),
+        Spec::new_sharpen(0.0, 0),
+        Spec::new_brightness(300),
+        Spec::new_saturation(2.0),
+        Spec::new_hue(f32::INFINITY),
+        Spec::new_padding(0, 9000, 0, 0, None),
+    ]
+}
+
+#[test]
+fn engines_should_agree_on_every_spec() {
+    for (spec, tolerance) in passing() {
+        let name = format!("{:?}", spec.data);
+        let specs = [spec];
+        let photon = render::<Photon>(&specs).unwrap_or_else(|e| panic!("photon {}: {}", name, e));
+        let image_rs = render::<ImageRs>(&specs).unwrap_or_else(|e| panic!("image-rs {}: {}", name, e));
+        assert_eq!(photon.dimensions(), image_rs.dimensions(), "{}", name);
+        if let Some(tolerance) = tolerance {
+            let diff = max_difference(&photon, &image_rs);
+            assert!(diff <= tolerance, "{}: channels differ by {}", name, diff);
+        }
+    }
+}
+
+#[test]
+fn engines_should_reject_the_same_specs() {
+    for spec in failing() {
+        let name = format!("{:?}", spec.data);
+        let specs = [spec];
+        let photon = render::<Photon>(&specs).err();
+        let image_rs = render::<ImageRs>(&specs).err();
+        match (photon, image_rs) {
+            (Some(ThumborError::Spec { reason: a, .. }), Some(ThumborError::Spec { reason: b, .. })) => {
+                assert_eq!(a, b, "{}", name)
+            }
+            (a, b) => panic!("{}: photon {:?}, image-rs {:?}", name, a, b),
+        }
+    }
+}
+
+#[test]
+fn engines_should_report_the_failing_spec() {
+    let specs = [Spec::new_grayscale(), Spec::new_blur(0)];
+    for result in [render::<Photon>(&specs), render::<ImageRs>(&specs)] {
+        assert!(matches!(result, Err(ThumborError::Spec { index: 1, name: "blur", .. })));
+    }
+}
+
+#[test]
+fn engines_should_encode_every_format() {
+    let specs = [Spec::new_resize(24, 16, SampleFilter::Triangle)];
+    let avif = crate::format::HAS_AVIF.then_some(OutputFormat::Avif(60));
+    for format in [OutputFormat::Jpeg(80), OutputFormat::Png, OutputFormat::WebP(80)].into_iter().chain(avif) {
+        for kind in [EngineKind::Photon, EngineKind::ImageRs] {
+            let data = kind.run(sample(), &specs, format).unwrap();
+            assert!(!data.is_empty(), "{:?} {:?}", kind, format);
+            // the image crate has no avif decoder without dav1d
+            if !matches!(format, OutputFormat::Avif(_)) {
+                let img = image::load_from_memory(&data).unwrap();
+                assert_eq!((img.width(), img.height()), (24, 16), "{:?} {:?}", kind, format);
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "avif"))]
+#[test]
+fn avif_is_refused_without_its_feature() {
+    let specs = [Spec::new_resize(24, 16, SampleFilter::Triangle)];
+    for kind in [EngineKind::Photon, EngineKind::ImageRs] {
+        let err = kind.run(sample(), &specs, OutputFormat::Avif(60)).unwrap_err();
+        assert!(matches!(err, ThumborError::UnsupportedFormat("image/avif")), "{:?}", kind);
+    }
+}
+
+#[test]
+fn engines_should_process_every_frame() {
+    let specs = [Spec::new_resize(12, 8, SampleFilter::Nearest), spec(spec::Data::Fliph(Fliph {}))];
+    for kind in [EngineKind::Photon, EngineKind::ImageRs] {
+        let data = kind.run(animation(), &specs, OutputFormat::Gif).unwrap();
+        let decoder = image::codecs::gif::GifDecoder::new(Cursor::new(data)).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 3, "{:?}", kind);
+        assert_eq!(frames[1].buffer().dimensions(), (12, 8), "{:?}", kind);
+        assert_eq!(frames[1].buffer().get_pixel(0, 0).0, [0, 0, 255, 255], "{:?}", kind);
+        assert_eq!(frames[2].delay().numer_denom_ms(), (80, 1), "{:?}", kind);
+
+        // formats that cannot animate get the first frame
+        let png = kind.run(animation(), &specs, OutputFormat::Png).unwrap();
+        assert_eq!(image::load_from_memory(&png).unwrap().to_rgba8().get_pixel(0, 0).0, [255, 0, 0, 255]);
+    }
+}
+
+#[test]
+fn engines_should_extract_a_frame() {
+    let specs = [Spec::new_frame(2), Spec::new_resize(12, 8, SampleFilter::Nearest)];
+    for kind in [EngineKind::Photon, EngineKind::ImageRs] {
+        let data = kind.run(animation(), &specs, OutputFormat::Gif).unwrap();
+        assert!(!super::is_animated(&data), "{:?}", kind);
+        let img = image::load_from_memory(&data).unwrap().to_rgba8();
+        assert_eq!((img.dimensions(), img.get_pixel(0, 0).0), ((12, 8), [0, 255, 0, 255]), "{:?}", kind);
+
+        for (data, frame) in [(animation(), 3), (sample(), 1)] {
+            let err = kind.run(data, &[Spec::new_frame(frame)], OutputFormat::Png).unwrap_err();
+            assert!(matches!(err, ThumborError::Spec { index: 0, name: "frame", .. }), "{:?}", kind);
+        }
+        assert!(kind.run(sample(), &[Spec::new_frame(0)], OutputFormat::Png).is_ok());
+    }
+}
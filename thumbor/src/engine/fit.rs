@@ -0,0 +1,419 @@
+use anyhow::{anyhow, bail, Result};
+use image::{imageops, Rgba, RgbaImage};
+
+use super::check_dimensions;
+use crate::pb::resize::{Fit, Gravity, ResizeType, SampleFilter};
+use crate::pb::Resize;
+
+// smart gravities score a thumbnail no larger than this on its long side
+const SMART_SIZE: u32 = 128;
+const ENTROPY_BINS: usize = 32;
+
+type Line = Vec<Rgba<u8>>;
+
+/// Crop windows smart gravities picked, in order. An animation's later frames replay the
+/// first frame's; scored frame by frame, the window would move around and the animation jitter
+#[derive(Debug, Clone, Default)]
+pub struct Windows {
+    picked: Vec<(u32, u32)>,
+    next: usize,
+}
+
+impl Windows {
+    /// The same windows, replayed from the start on another frame
+    pub fn rewound(&self) -> Self {
+        Self {
+            picked: self.picked.clone(),
+            next: 0,
+        }
+    }
+
+    // Hands `pick` the window to reuse, if an earlier frame picked one here, and remembers new ones
+    fn pick(&mut self, pick: impl FnOnce(Option<(u32, u32)>) -> (u32, u32)) -> (u32, u32) {
+        let window = pick(self.picked.get(self.next).copied());
+        if self.next == self.picked.len() {
+            self.picked.push(window);
+        }
+        self.next += 1;
+        window
+    }
+}
+
+/// How a resize maps the source onto the requested box
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// scale to exactly this size
+    Scale(u32, u32),
+    /// scale, then crop the overflow down to `crop`
+    Cover { scale: (u32, u32), crop: (u32, u32) },
+    /// scale, then pad up to `canvas`
+    Contain { scale: (u32, u32), canvas: (u32, u32) },
+}
+
+impl Layout {
+    pub fn scale(&self) -> (u32, u32) {
+        match *self {
+            Layout::Scale(w, h) => (w, h),
+            Layout::Cover { scale, .. } | Layout::Contain { scale, .. } => scale,
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        match *self {
+            Layout::Scale(w, h) => (w, h),
+            Layout::Cover { crop, .. } => crop,
+            Layout::Contain { canvas, .. } => canvas,
+        }
+    }
+}
+
+/// A validated resize, shared by the engines
+#[derive(Debug, Clone, Copy)]
+pub struct Plan {
+    pub rtype: ResizeType,
+    pub filter: SampleFilter,
+    pub gravity: Gravity,
+    pub layout: Layout,
+}
+
+pub fn plan(source: (u32, u32), op: &Resize) -> Result<Plan> {
+    let rtype = ResizeType::from_i32(op.rtype).ok_or_else(|| anyhow!("unknown resize type {}", op.rtype))?;
+    let filter = SampleFilter::from_i32(op.filter).ok_or_else(|| anyhow!("unknown sample filter {}", op.filter))?;
+    let fit = Fit::from_i32(op.fit).ok_or_else(|| anyhow!("unknown fit {}", op.fit))?;
+    let gravity = Gravity::from_i32(op.gravity).ok_or_else(|| anyhow!("unknown gravity {}", op.gravity))?;
+    let layout = layout(source, (op.width, op.height), fit)?;
+    let (w, h) = layout.scale();
+    check_dimensions(w, h)?;
+    check_dimensions(layout.size().0, layout.size().1)?;
+
+    if rtype == ResizeType::SeamCarve {
+        if !matches!(layout, Layout::Scale(..)) {
+            bail!("seam carving only supports the fill, inside and outside fits");
+        }
+        // seam carving can only remove seams
+        if w > source.0 || h > source.1 {
+            bail!("seam carving cannot enlarge a {}x{} image to {}x{}", source.0, source.1, w, h);
+        }
+    }
+    Ok(Plan {
+        rtype,
+        filter,
+        gravity,
+        layout,
+    })
+}
+
+// Crop or pad an image scaled to `plan.layout.scale()` into its final size
+pub fn finish(img: RgbaImage, plan: &Plan, background: Rgba<u8>, windows: &mut Windows) -> RgbaImage {
+    match plan.layout {
+        Layout::Scale(..) => img,
+        Layout::Cover { crop: (cw, ch), .. } => {
+            let (x, y) = match plan.gravity {
+                Gravity::Entropy | Gravity::Attention => {
+                    windows.pick(|window| crop_offset(&img, (cw, ch), plan.gravity, window))
+                }
+                gravity => crop_offset(&img, (cw, ch), gravity, None),
+            };
+            imageops::crop_imm(&img, x, y, cw, ch).to_image()
+        }
+        Layout::Contain { canvas: (cw, ch), .. } => {
+            let mut canvas = RgbaImage::from_pixel(cw, ch, background);
+            let (x, y) = gravity_offset(plan.gravity, (cw, ch), img.dimensions());
+            imageops::overlay(&mut canvas, &img, x as i64, y as i64);
+            canvas
+        }
+    }
+}
+
+// A zero width or height is derived from the other one, keeping the aspect ratio
+pub fn layout(source: (u32, u32), target: (u32, u32), fit: Fit) -> Result<Layout> {
+    let (sw, sh) = (source.0 as f64, source.1 as f64);
+    let (w, h) = match target {
+        (0, 0) => bail!("resize needs a width or a height"),
+        (w, 0) => return Ok(Layout::Scale(w, scaled(sh * w as f64 / sw))),
+        (0, h) => return Ok(Layout::Scale(scaled(sw * h as f64 / sh), h)),
+        size => size,
+    };
+
+    let (fw, fh) = (w as f64 / sw, h as f64 / sh);
+    let size = |factor: f64| (scaled(sw * factor), scaled(sh * factor));
+    // rounding may leave a pixel too many or too few on the fitted side
+    let within = |(a, b): (u32, u32)| (a.min(w), b.min(h));
+    let beyond = |(a, b): (u32, u32)| (a.max(w), b.max(h));
+
+    Ok(match fit {
+        Fit::Fill => Layout::Scale(w, h),
+        Fit::Inside => {
+            let (a, b) = within(size(fw.min(fh)));
+            Layout::Scale(a, b)
+        }
+        Fit::Outside => {
+            let (a, b) = beyond(size(fw.max(fh)));
+            Layout::Scale(a, b)
+        }
+        Fit::Contain => Layout::Contain {
+            scale: within(size(fw.min(fh))),
+            canvas: (w, h),
+        },
+        Fit::Cover => Layout::Cover {
+            scale: beyond(size(fw.max(fh))),
+            crop: (w, h),
+        },
+    })
+}
+
+fn scaled(v: f64) -> u32 {
+    v.round().clamp(1.0, u32::MAX as f64) as u32
+}
+
+// Top left corner of an `inner` box placed inside `outer` at a compass gravity;
+// smart gravities fall back to the center
+pub fn gravity_offset(gravity: Gravity, outer: (u32, u32), inner: (u32, u32)) -> (u32, u32) {
+    let (dx, dy) = (outer.0.saturating_sub(inner.0), outer.1.saturating_sub(inner.1));
+    let x = match gravity {
+        Gravity::West | Gravity::NorthWest | Gravity::SouthWest => 0,
+        Gravity::East | Gravity::NorthEast | Gravity::SouthEast => dx,
+        _ => dx / 2,
+    };
+    let y = match gravity {
+        Gravity::North | Gravity::NorthWest | Gravity::NorthEast => 0,
+        Gravity::South | Gravity::SouthWest | Gravity::SouthEast => dy,
+        _ => dy / 2,
+    };
+    (x, y)
+}
+
+// Top left corner of a `crop` sized window in the image; smart gravities take `window` when
+// given instead of scoring the image
+pub fn crop_offset(img: &RgbaImage, crop: (u32, u32), gravity: Gravity, window: Option<(u32, u32)>) -> (u32, u32) {
+    let (width, height) = img.dimensions();
+    let centered = gravity_offset(Gravity::Center, (width, height), crop);
+    let (dx, dy) = (width.saturating_sub(crop.0), height.saturating_sub(crop.1));
+    let score: fn(&[Line], usize) -> Vec<f32> = match gravity {
+        Gravity::Entropy => entropy_scores,
+        Gravity::Attention => attention_scores,
+        _ => return gravity_offset(gravity, (width, height), crop),
+    };
+    if dx == 0 && dy == 0 {
+        return centered;
+    }
+    match window {
+        Some((x, y)) => (x.min(dx), y.min(dy)),
+        None => smart_offset(img, crop, score, centered),
+    }
+}
+
+fn smart_offset(img: &RgbaImage, crop: (u32, u32), score: fn(&[Line], usize) -> Vec<f32>, centered: (u32, u32)) -> (u32, u32) {
+    let (width, height) = img.dimensions();
+    let (dx, dy) = (width.saturating_sub(crop.0), height.saturating_sub(crop.1));
+
+    // a cover crop only overflows along one axis, so slide the window along that one
+    let along_x = dx >= dy;
+    let ratio = (SMART_SIZE as f32 / width.max(height) as f32).min(1.0);
+    let small = imageops::thumbnail(
+        img,
+        ((width as f32 * ratio).round() as u32).max(1),
+        ((height as f32 * ratio).round() as u32).max(1),
+    );
+    let lines = lines(&small, along_x);
+    let (len, small_len, window) = if along_x {
+        (width, small.width(), crop.0)
+    } else {
+        (height, small.height(), crop.1)
+    };
+    let ratio = small_len as f32 / len as f32;
+    let window = ((window as f32 * ratio).round() as usize).clamp(1, lines.len());
+
+    let best = pick(&score(&lines, window));
+    let pos = ((best as f32 / ratio).round() as u32).min(if along_x { dx } else { dy });
+    if along_x {
+        (pos, centered.1)
+    } else {
+        (centered.0, pos)
+    }
+}
+
+// Columns of the image when sliding horizontally, rows otherwise
+fn lines(img: &RgbaImage, columns: bool) -> Vec<Line> {
+    let (width, height) = img.dimensions();
+    if columns {
+        (0..width)
+            .map(|x| (0..height).map(|y| *img.get_pixel(x, y)).collect())
+            .collect()
+    } else {
+        img.rows().map(|row| row.copied().collect()).collect()
+    }
+}
+
+fn luma(p: &Rgba<u8>) -> f32 {
+    0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32
+}
+
+// Shannon entropy of the luminance histogram of every window position
+fn entropy_scores(lines: &[Line], window: usize) -> Vec<f32> {
+    let histograms: Vec<[u32; ENTROPY_BINS]> = lines
+        .iter()
+        .map(|line| {
+            let mut bins = [0u32; ENTROPY_BINS];
+            for p in line {
+                bins[(luma(p) as usize * ENTROPY_BINS / 256).min(ENTROPY_BINS - 1)] += 1;
+            }
+            bins
+        })
+        .collect();
+
+    let mut bins = [0u32; ENTROPY_BINS];
+    let mut scores = Vec::with_capacity(lines.len() - window + 1);
+    for (i, hist) in histograms.iter().enumerate() {
+        bins.iter_mut().zip(hist).for_each(|(b, h)| *b += h);
+        if i >= window {
+            bins.iter_mut().zip(&histograms[i - window]).for_each(|(b, h)| *b -= h);
+        }
+        if i + 1 >= window {
+            let total = bins.iter().sum::<u32>() as f32;
+            let entropy = bins
+                .iter()
+                .filter(|&&n| n > 0)
+                .map(|&n| {
+                    let p = n as f32 / total;
+                    -p * p.log2()
+                })
+                .sum();
+            scores.push(entropy);
+        }
+    }
+    scores
+}
+
+// Edges plus color saturation of every window position, a cheap stand-in for saliency
+fn attention_scores(lines: &[Line], window: usize) -> Vec<f32> {
+    let line_scores: Vec<f32> = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            line.iter()
+                .enumerate()
+                .map(|(j, p)| {
+                    let l = luma(p);
+                    let across = lines.get(i + 1).map(|next| (l - luma(&next[j])).abs());
+                    let along = line.get(j + 1).map(|next| (l - luma(next)).abs());
+                    let rgb = [p[0], p[1], p[2]];
+                    let saturation = (rgb.iter().max().unwrap() - rgb.iter().min().unwrap()) as f32;
+                    (across.unwrap_or(0.0) + along.unwrap_or(0.0) + saturation) * p[3] as f32 / 255.0
+                })
+                .sum()
+        })
+        .collect();
+
+    line_scores
+        .windows(window)
+        .map(|w| w.iter().sum())
+        .collect()
+}
+
+// Index of the best score, preferring the most central one on ties
+fn pick(scores: &[f32]) -> usize {
+    let center = (scores.len() - 1) as f32 / 2.0;
+    let mut best = 0;
+    for (i, &score) in scores.iter().enumerate() {
+        let diff = score - scores[best];
+        let closer = (i as f32 - center).abs() < (best as f32 - center).abs();
+        if diff > 1e-3 || (diff.abs() <= 1e-3 && closer) {
+            best = i;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_side_keeps_aspect_ratio() {
+        assert_eq!(layout((1000, 500), (300, 0), Fit::Cover).unwrap(), Layout::Scale(300, 150));
+        assert_eq!(layout((1000, 500), (0, 100), Fit::Fill).unwrap(), Layout::Scale(200, 100));
+        assert!(layout((1000, 500), (0, 0), Fit::Fill).is_err());
+    }
+
+    #[test]
+    fn fits_should_work() {
+        let src = (1000, 500);
+        assert_eq!(layout(src, (300, 300), Fit::Fill).unwrap(), Layout::Scale(300, 300));
+        assert_eq!(layout(src, (300, 300), Fit::Inside).unwrap(), Layout::Scale(300, 150));
+        assert_eq!(layout(src, (300, 300), Fit::Outside).unwrap(), Layout::Scale(600, 300));
+        assert_eq!(
+            layout(src, (300, 300), Fit::Contain).unwrap(),
+            Layout::Contain { scale: (300, 150), canvas: (300, 300) }
+        );
+        assert_eq!(
+            layout(src, (300, 300), Fit::Cover).unwrap(),
+            Layout::Cover { scale: (600, 300), crop: (300, 300) }
+        );
+    }
+
+    #[test]
+    fn compass_gravity_should_work() {
+        let (outer, inner) = ((600, 300), (300, 300));
+        assert_eq!(gravity_offset(Gravity::Center, outer, inner), (150, 0));
+        assert_eq!(gravity_offset(Gravity::West, outer, inner), (0, 0));
+        assert_eq!(gravity_offset(Gravity::SouthEast, outer, inner), (300, 0));
+        assert_eq!(gravity_offset(Gravity::South, (300, 600), inner), (0, 300));
+    }
+
+    #[test]
+    fn smart_gravity_finds_the_detail() {
+        // flat gray with a noisy, colorful patch on the right
+        let img = RgbaImage::from_fn(400, 100, |x, y| {
+            if x >= 300 {
+                let v = ((x * 37 + y * 91) % 256) as u8;
+                Rgba([v, 255 - v, (v / 2).wrapping_mul(3), 255])
+            } else {
+                Rgba([128, 128, 128, 255])
+            }
+        });
+        for gravity in [Gravity::Entropy, Gravity::Attention] {
+            let (x, y) = crop_offset(&img, (100, 100), gravity, None);
+            // entropy likes a little of the flat area too, so only check the window moved over
+            assert!(x >= 250, "{:?} picked x = {}", gravity, x);
+            assert_eq!(y, 0);
+        }
+    }
+
+    #[test]
+    fn smart_gravity_centers_flat_images() {
+        let img = RgbaImage::from_pixel(400, 100, Rgba([10, 20, 30, 255]));
+        assert_eq!(crop_offset(&img, (100, 100), Gravity::Entropy, None), (150, 0));
+        assert_eq!(crop_offset(&img, (100, 100), Gravity::Attention, None), (150, 0));
+    }
+
+    #[test]
+    fn frames_should_share_the_first_frames_window() {
+        let detailed = RgbaImage::from_fn(400, 100, |x, y| match x >= 300 {
+            true => Rgba([((x * 37 + y * 91) % 256) as u8, 0, 0, 255]),
+            false => Rgba([128, 128, 128, 255]),
+        });
+        let mirrored = imageops::flip_horizontal(&detailed);
+        let plan = Plan {
+            rtype: ResizeType::Normal,
+            filter: SampleFilter::Nearest,
+            gravity: Gravity::Entropy,
+            layout: Layout::Cover { scale: (400, 100), crop: (100, 100) },
+        };
+        let background = Rgba([0, 0, 0, 0]);
+        let mut windows = Windows::default();
+        finish(detailed.clone(), &plan, background, &mut windows);
+        let (x, y) = windows.picked[0];
+        assert!(x >= 250, "picked x = {}", x);
+
+        // scored alone, the mirrored frame would be cropped on the left
+        assert!(crop_offset(&mirrored, (100, 100), Gravity::Entropy, None).0 <= 50);
+        let mut replayed = windows.rewound();
+        let cropped = finish(mirrored.clone(), &plan, background, &mut replayed);
+        assert_eq!(cropped, imageops::crop_imm(&mirrored, x, y, 100, 100).to_image());
+        assert_eq!(replayed.picked, windows.picked);
+
+        // a window past the edge is clamped
+        assert_eq!(crop_offset(&mirrored, (100, 100), Gravity::Entropy, Some((999, 0))), (300, 0));
+    }
+}
@@ -0,0 +1,388 @@
+use crate::pb::Spec;
+use crate::pb::{Resize, Crop, Fliph, Flipv, Contrast, Filter, Watermark};
+use crate::pb::{Rotate, Blur, Sharpen, Grayscale, Sepia, Brightness, Saturation, Hue, Padding, RoundCorners, Text};
+use crate::pb::{resize, filter};
+
+use super::fit;
+use super::rgba;
+use super::{Engine, SpecTransform};
+use crate::error::ThumborError;
+use crate::metadata;
+use crate::format::OutputFormat;
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use std::time::Duration;
+use image::{imageops, Rgba, RgbaImage};
+
+// how strongly the named filters mix in their color, as in photon
+const FILTER_OPACITY: f32 = 0.2;
+
+/// An engine on the `image` crate alone, without photon's wasm oriented pipeline
+pub struct ImageRs(RgbaImage, fit::Windows);
+
+impl TryFrom<Bytes> for ImageRs {
+    type Error = ThumborError;
+
+    fn try_from(data: Bytes) -> Result<Self, Self::Error> {
+        let img = image::load_from_memory(&data)
+            .map_err(|e| ThumborError::Decode(e.to_string()))?
+            .into_rgba8();
+        let img = match metadata::orientation(&data) {
+            Some(orientation) if orientation != 1 => metadata::orient(img, orientation),
+            _ => img,
+        };
+        Ok(Self(img, fit::Windows::default()))
+    }
+}
+
+impl From<RgbaImage> for ImageRs {
+    fn from(img: RgbaImage) -> Self {
+        Self(img, fit::Windows::default())
+    }
+}
+
+impl From<ImageRs> for RgbaImage {
+    fn from(engine: ImageRs) -> Self {
+        engine.0
+    }
+}
+
+impl Engine for ImageRs {
+    fn apply(&mut self, specs: &[Spec]) -> Result<Vec<Duration>, ThumborError> {
+        super::apply_specs(self, specs)
+    }
+
+    fn windows(&mut self) -> &mut fit::Windows {
+        &mut self.1
+    }
+
+    fn generate(self, format: OutputFormat) -> Result<Vec<u8>, ThumborError> {
+        rgba::encode(self.0, format).map_err(|e| ThumborError::Encode(e.to_string()))
+    }
+}
+
+impl SpecTransform<&Crop> for ImageRs {
+    fn transform(&mut self, op: &Crop) -> Result<()> {
+        let (width, height) = self.0.dimensions();
+        if op.x1 >= op.x2 || op.y1 >= op.y2 || op.x2 > width || op.y2 > height {
+            bail!(
+                "crop area ({}, {}) - ({}, {}) is outside of the {}x{} image",
+                op.x1, op.y1, op.x2, op.y2, width, height
+            );
+        }
+        self.0 = imageops::crop_imm(&self.0, op.x1, op.y1, op.x2 - op.x1, op.y2 - op.y1).to_image();
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Contrast> for ImageRs {
+    fn transform(&mut self, op: &Contrast) -> Result<()> {
+        if !(-255.0..=255.0).contains(&op.contrast) {
+            bail!("contrast {} is not between -255 and 255", op.contrast);
+        }
+        let factor = 259.0 * (op.contrast + 255.0) / (255.0 * (259.0 - op.contrast));
+        map_rgb(&mut self.0, |v| (factor * (v as f32 - 128.0) + 128.0).round().clamp(0.0, 255.0) as u8);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Flipv> for ImageRs {
+    fn transform(&mut self, _op: &Flipv) -> Result<()> {
+        imageops::flip_vertical_in_place(&mut self.0);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Fliph> for ImageRs {
+    fn transform(&mut self, _op: &Fliph) -> Result<()> {
+        imageops::flip_horizontal_in_place(&mut self.0);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Filter> for ImageRs {
+    fn transform(&mut self, op: &Filter) -> Result<()> {
+        let color = match filter::Filter::from_i32(op.filter) {
+            Some(filter::Filter::Unspecified) => return Ok(()),
+            Some(filter::Filter::Oceanic) => [0.0, 89.0, 173.0],
+            Some(filter::Filter::Islands) => [0.0, 24.0, 95.0],
+            Some(filter::Filter::Marine) => [0.0, 14.0, 119.0],
+            None => bail!("unknown filter {}", op.filter),
+        };
+        for p in self.0.pixels_mut() {
+            for i in 0..3 {
+                let v = p[i] as f32;
+                p[i] = (v + (color[i] - v) * FILTER_OPACITY).round() as u8;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Resize> for ImageRs {
+    fn transform(&mut self, op: &Resize) -> Result<()> {
+        let plan = fit::plan(self.0.dimensions(), op)?;
+        let (w, h) = plan.layout.scale();
+        self.0 = match plan.rtype {
+            resize::ResizeType::Normal => {
+                let img = imageops::resize(&self.0, w, h, plan.filter.into());
+                fit::finish(img, &plan, rgba::background(op.background.as_ref()), &mut self.1)
+            }
+            resize::ResizeType::SeamCarve => seam_carve(&self.0, w, h),
+        };
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Watermark> for ImageRs {
+    fn transform(&mut self, op: &Watermark) -> Result<()> {
+        rgba::watermark(&mut self.0, op)
+    }
+}
+
+impl SpecTransform<&Text> for ImageRs {
+    fn transform(&mut self, op: &Text) -> Result<()> {
+        rgba::text(&mut self.0, op)
+    }
+}
+
+impl SpecTransform<&Rotate> for ImageRs {
+    fn transform(&mut self, op: &Rotate) -> Result<()> {
+        let img = std::mem::take(&mut self.0);
+        self.0 = rgba::rotate(img, op)?;
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Blur> for ImageRs {
+    fn transform(&mut self, op: &Blur) -> Result<()> {
+        if op.radius == 0 || op.radius > 100 {
+            bail!("blur radius {} is not between 1 and 100", op.radius);
+        }
+        self.0 = imageops::blur(&self.0, op.radius as f32);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Sharpen> for ImageRs {
+    fn transform(&mut self, op: &Sharpen) -> Result<()> {
+        self.0 = rgba::sharpen(&self.0, op)?;
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Grayscale> for ImageRs {
+    fn transform(&mut self, _op: &Grayscale) -> Result<()> {
+        for p in self.0.pixels_mut() {
+            let avg = ((p[0] as u32 + p[1] as u32 + p[2] as u32) / 3) as u8;
+            p[0] = avg;
+            p[1] = avg;
+            p[2] = avg;
+        }
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Sepia> for ImageRs {
+    fn transform(&mut self, _op: &Sepia) -> Result<()> {
+        rgba::sepia(&mut self.0);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Brightness> for ImageRs {
+    fn transform(&mut self, op: &Brightness) -> Result<()> {
+        rgba::brighten(&mut self.0, op)
+    }
+}
+
+impl SpecTransform<&Saturation> for ImageRs {
+    fn transform(&mut self, op: &Saturation) -> Result<()> {
+        if !(-1.0..=1.0).contains(&op.saturation) {
+            bail!("saturation {} is not between -1 and 1", op.saturation);
+        }
+        if op.saturation != 0.0 {
+            // relative, like photon: 0.5 makes colors half again as saturated
+            let factor = 1.0 + op.saturation;
+            map_hsl(&mut self.0, |[h, s, l]| [h, (s * factor).clamp(0.0, 1.0), l]);
+        }
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Hue> for ImageRs {
+    fn transform(&mut self, op: &Hue) -> Result<()> {
+        if !op.degrees.is_finite() {
+            bail!("hue rotation {} is not a number", op.degrees);
+        }
+        let turn = op.degrees.rem_euclid(360.0) / 360.0;
+        if turn != 0.0 {
+            map_hsl(&mut self.0, |[h, s, l]| [(h + turn).fract(), s, l]);
+        }
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Padding> for ImageRs {
+    fn transform(&mut self, op: &Padding) -> Result<()> {
+        self.0 = rgba::pad(&self.0, op)?;
+        Ok(())
+    }
+}
+
+impl SpecTransform<&RoundCorners> for ImageRs {
+    fn transform(&mut self, op: &RoundCorners) -> Result<()> {
+        rgba::round_corners(&mut self.0, op);
+        Ok(())
+    }
+}
+
+fn map_rgb(img: &mut RgbaImage, f: impl Fn(u8) -> u8) {
+    for p in img.pixels_mut() {
+        for i in 0..3 {
+            p[i] = f(p[i]);
+        }
+    }
+}
+
+// Run `f` on each pixel as hue, saturation and lightness, all between 0 and 1
+fn map_hsl(img: &mut RgbaImage, f: impl Fn([f32; 3]) -> [f32; 3]) {
+    for p in img.pixels_mut() {
+        let [r, g, b] = hsl_to_rgb(f(rgb_to_hsl([p[0], p[1], p[2]])));
+        *p = Rgba([r, g, b, p[3]]);
+    }
+}
+
+fn rgb_to_hsl(rgb: [u8; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(|v| v as f32 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d == 0.0 {
+        return [0.0, 0.0, l];
+    }
+
+    let s = d / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        ((g - b) / d).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    [h / 6.0, s, l]
+}
+
+fn hsl_to_rgb([h, s, l]: [f32; 3]) -> [u8; 3] {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let h = h * 6.0;
+    let x = c * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    [r, g, b].map(|v| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+// Shrink to `width`x`height` by removing the lowest energy seams, columns first
+fn seam_carve(img: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let mut img = img.clone();
+    while img.width() > width {
+        img = remove_seam(&img);
+    }
+    if img.height() > height {
+        // rows are columns of the transposed image
+        img = imageops::rotate90(&img);
+        while img.width() > height {
+            img = remove_seam(&img);
+        }
+        img = imageops::rotate270(&img);
+    }
+    img
+}
+
+// Remove the connected top to bottom path with the least total gradient
+fn remove_seam(img: &RgbaImage) -> RgbaImage {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let luma = |x: usize, y: usize| {
+        let p = img.get_pixel(x.min(w - 1) as u32, y.min(h - 1) as u32);
+        0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32
+    };
+
+    // cumulative energy, each cell adding the cheapest of the three cells above
+    let mut cost = vec![0f32; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let dx = luma(x + 1, y) - luma(x.saturating_sub(1), y);
+            let dy = luma(x, y + 1) - luma(x, y.saturating_sub(1));
+            let above = if y == 0 {
+                0.0
+            } else {
+                let row = &cost[(y - 1) * w..y * w];
+                row[x.saturating_sub(1)..(x + 2).min(w)]
+                    .iter()
+                    .copied()
+                    .fold(f32::MAX, f32::min)
+            };
+            cost[y * w + x] = dx.abs() + dy.abs() + above;
+        }
+    }
+
+    // walk back up from the cheapest bottom cell
+    let mut seam = vec![0usize; h];
+    seam[h - 1] = cheapest(&cost[(h - 1) * w..], 0);
+    for y in (0..h - 1).rev() {
+        let x = seam[y + 1];
+        let from = x.saturating_sub(1);
+        seam[y] = cheapest(&cost[y * w + from..y * w + (x + 2).min(w)], from);
+    }
+
+    RgbaImage::from_fn(w as u32 - 1, h as u32, |x, y| {
+        let skip = (x as usize >= seam[y as usize]) as u32;
+        *img.get_pixel(x + skip, y)
+    })
+}
+
+fn cheapest(costs: &[f32], offset: usize) -> usize {
+    let (index, _) = costs
+        .iter()
+        .enumerate()
+        .fold((0, f32::MAX), |best, (i, &c)| if c < best.1 { (i, c) } else { best });
+    offset + index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hsl_should_round_trip() {
+        for rgb in [[0, 0, 0], [255, 255, 255], [255, 0, 0], [12, 200, 99], [90, 40, 250]] {
+            assert_eq!(hsl_to_rgb(rgb_to_hsl(rgb)), rgb);
+        }
+    }
+
+    #[test]
+    fn seam_carve_should_keep_the_busy_part() {
+        // flat gray with a band of noise in columns 4..8
+        let img = RgbaImage::from_fn(12, 6, |x, y| {
+            if (4..8).contains(&x) {
+                let v = ((x * 97 + y * 57) % 256) as u8;
+                Rgba([v, v, v, 255])
+            } else {
+                Rgba([128, 128, 128, 255])
+            }
+        });
+        let carved = seam_carve(&img, 6, 4);
+        assert_eq!(carved.dimensions(), (6, 4));
+        for y in 0..4 {
+            let busy = (0..6).filter(|&x| carved.get_pixel(x, y)[0] != 128).count();
+            assert_eq!(busy, 4, "row {}", y);
+        }
+    }
+}
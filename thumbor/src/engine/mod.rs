@@ -1,15 +1,209 @@
-use image::ImageOutputFormat;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use crate::pb::Spec;
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use image::{Frame, RgbaImage};
 
+use crate::error::ThumborError;
+use crate::format::{OutputFormat, HAS_AVIF};
+use crate::metrics::METRICS;
+use crate::pb::{spec, Spec};
+use crate::pb::{Resize, Crop, Fliph, Flipv, Contrast, Filter, Watermark};
+use crate::pb::{Rotate, Blur, Sharpen, Grayscale, Sepia, Brightness, Saturation, Hue, Padding, RoundCorners, Text};
+
+mod animation;
+mod fit;
+mod image_rs;
+mod overlay;
 mod photon;
+mod rgba;
+pub use animation::is_animated;
+pub use image_rs::ImageRs;
 pub use photon::Photon;
 
+#[cfg(test)]
+mod conformance;
+
+const MAX_DIMENSION: u32 = 8192;
+
 pub trait Engine {
-    fn apply(&mut self, specs: &[Spec]);
-    fn generate(self, format: ImageOutputFormat) -> Vec<u8>;
+    // how long each spec took, in order
+    fn apply(&mut self, specs: &[Spec]) -> Result<Vec<Duration>, ThumborError>;
+    // crop windows smart gravities picked, for an animation's later frames to replay
+    fn windows(&mut self) -> &mut fit::Windows;
+    fn generate(self, format: OutputFormat) -> Result<Vec<u8>, ThumborError>;
 }
 
 pub trait SpecTransform<T> {
-    fn transform(&mut self, op: T);
+    fn transform(&mut self, op: T) -> Result<()>;
+}
+
+/// Which `Engine` processes images
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EngineKind {
+    #[default]
+    Photon,
+    ImageRs,
+}
+
+impl FromStr for EngineKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "photon" => Ok(EngineKind::Photon),
+            "image" | "image-rs" => Ok(EngineKind::ImageRs),
+            _ => bail!("unknown engine {:?}, expected photon or image", s),
+        }
+    }
+}
+
+impl EngineKind {
+    pub fn run(self, data: Bytes, specs: &[Spec], format: OutputFormat) -> Result<Vec<u8>, ThumborError> {
+        if matches!(format, OutputFormat::Avif(_)) && !HAS_AVIF {
+            return Err(ThumborError::UnsupportedFormat(format.content_type()));
+        }
+        match self {
+            EngineKind::Photon => run::<Photon>(data, specs, format),
+            EngineKind::ImageRs => run::<ImageRs>(data, specs, format),
+        }
+    }
+}
+
+fn run<E>(data: Bytes, specs: &[Spec], format: OutputFormat) -> Result<Vec<u8>, ThumborError>
+where
+    E: Engine + TryFrom<Bytes, Error = ThumborError> + From<RgbaImage> + Into<RgbaImage>,
+{
+    // a frame spec picks its frame before anything else runs
+    let frame = specs.iter().enumerate().find_map(|(index, spec)| match &spec.data {
+        Some(spec::Data::Frame(frame)) => Some((index, frame.index)),
+        _ => None,
+    });
+    // only decode every frame when they are all needed, other formats get the first one
+    let frames = match frame.is_some() || format.can_animate() {
+        true => animation::decode(&data)?,
+        false => None,
+    };
+
+    match (frames, frame) {
+        (None, None) | (None, Some((_, 0))) => {
+            let mut engine = E::try_from(data)?;
+            record(specs, &engine.apply(specs)?);
+            engine.generate(format)
+        }
+        (frames, Some((index, n))) => {
+            // a still source has the one frame, which the first arm took care of
+            let mut frames = frames.unwrap_or_default();
+            if n as usize >= frames.len() {
+                return Err(ThumborError::Spec {
+                    index,
+                    name: "frame",
+                    reason: format!("frame {} is out of range, the source has {}", n, frames.len().max(1)),
+                });
+            }
+            let mut engine = E::from(frames.swap_remove(n as usize).into_buffer());
+            record(specs, &engine.apply(specs)?);
+            engine.generate(format)
+        }
+        (Some(frames), None) => {
+            let mut total = vec![Duration::ZERO; specs.len()];
+            // every frame is cropped at the windows smart gravities picked on the first one
+            let mut windows: Option<fit::Windows> = None;
+            let frames = frames
+                .into_iter()
+                .map(|frame| {
+                    let delay = frame.delay();
+                    let mut engine = E::from(frame.into_buffer());
+                    if let Some(windows) = &windows {
+                        *engine.windows() = windows.rewound();
+                    }
+                    for (total, elapsed) in total.iter_mut().zip(engine.apply(specs)?) {
+                        *total += elapsed;
+                    }
+                    windows.get_or_insert_with(|| engine.windows().rewound());
+                    Ok(Frame::from_parts(engine.into(), 0, 0, delay))
+                })
+                .collect::<Result<Vec<_>, ThumborError>>()?;
+            record(specs, &total);
+            animation::encode(frames, format).map_err(|e| ThumborError::Encode(e.to_string()))
+        }
+    }
+}
+
+// One observation per spec and request, however many frames it ran on
+fn record(specs: &[Spec], timings: &[Duration]) {
+    for (spec, elapsed) in specs.iter().zip(timings) {
+        if let Some(data) = &spec.data {
+            METRICS.transform(data.name(), *elapsed);
+        }
+    }
+}
+
+// Apply specs in order, reporting which one failed and why
+fn apply_specs<E>(engine: &mut E, specs: &[Spec]) -> Result<Vec<Duration>, ThumborError>
+where
+    E: for<'a> SpecTransform<&'a Resize>
+        + for<'a> SpecTransform<&'a Crop>
+        + for<'a> SpecTransform<&'a Flipv>
+        + for<'a> SpecTransform<&'a Fliph>
+        + for<'a> SpecTransform<&'a Contrast>
+        + for<'a> SpecTransform<&'a Filter>
+        + for<'a> SpecTransform<&'a Watermark>
+        + for<'a> SpecTransform<&'a Rotate>
+        + for<'a> SpecTransform<&'a Blur>
+        + for<'a> SpecTransform<&'a Sharpen>
+        + for<'a> SpecTransform<&'a Grayscale>
+        + for<'a> SpecTransform<&'a Sepia>
+        + for<'a> SpecTransform<&'a Brightness>
+        + for<'a> SpecTransform<&'a Saturation>
+        + for<'a> SpecTransform<&'a Hue>
+        + for<'a> SpecTransform<&'a Padding>
+        + for<'a> SpecTransform<&'a RoundCorners>
+        + for<'a> SpecTransform<&'a Text>,
+{
+    let mut timings = Vec::with_capacity(specs.len());
+    for (index, spec) in specs.iter().enumerate() {
+        let Some(data) = spec.data.as_ref() else {
+            timings.push(Duration::ZERO);
+            continue;
+        };
+        let started = Instant::now();
+        let result = match data {
+            spec::Data::Crop(v) => engine.transform(v),
+            spec::Data::Contrast(v) => engine.transform(v),
+            spec::Data::Filter(v) => engine.transform(v),
+            spec::Data::Fliph(v) => engine.transform(v),
+            spec::Data::Flipv(v) => engine.transform(v),
+            spec::Data::Resize(v) => engine.transform(v),
+            spec::Data::Watermark(v) => engine.transform(v),
+            spec::Data::Rotate(v) => engine.transform(v),
+            spec::Data::Blur(v) => engine.transform(v),
+            spec::Data::Sharpen(v) => engine.transform(v),
+            spec::Data::Grayscale(v) => engine.transform(v),
+            spec::Data::Sepia(v) => engine.transform(v),
+            spec::Data::Brightness(v) => engine.transform(v),
+            spec::Data::Saturation(v) => engine.transform(v),
+            spec::Data::Hue(v) => engine.transform(v),
+            spec::Data::Padding(v) => engine.transform(v),
+            spec::Data::RoundCorners(v) => engine.transform(v),
+            spec::Data::Text(v) => engine.transform(v),
+            // picked by `run` while decoding
+            spec::Data::Frame(_) => Ok(()),
+        };
+        timings.push(started.elapsed());
+        result.map_err(|e| ThumborError::Spec {
+            index,
+            name: data.name(),
+            reason: e.to_string(),
+        })?;
+    }
+    Ok(timings)
+}
+
+fn check_dimensions(width: u32, height: u32) -> Result<()> {
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        bail!("size {}x{} is not between 1 and {}", width, height, MAX_DIMENSION);
+    }
+    Ok(())
 }
@@ -0,0 +1,203 @@
+use anyhow::{anyhow, bail, Result};
+use image::{imageops, Rgba, RgbaImage};
+use rusttype::{point, Font, Scale};
+
+use crate::pb::{Anchor, Text, Watermark};
+
+const DEFAULT_TEXT_SIZE: f32 = 24.0;
+const MAX_TEXT_SIZE: f32 = 1000.0;
+
+// Top left corner of an `inner` box placed at the anchor of `outer`, moved by `offset` towards the center
+pub fn position(anchor: Anchor, outer: (u32, u32), inner: (u32, u32), offset: (u32, u32)) -> (i64, i64) {
+    let axis = |side: i8, outer: u32, inner: u32, offset: u32| -> i64 {
+        let (outer, inner, offset) = (outer as i64, inner as i64, offset as i64);
+        match side {
+            -1 => offset,
+            1 => outer - inner - offset,
+            _ => (outer - inner) / 2 + offset,
+        }
+    };
+    let (col, row) = match anchor {
+        Anchor::TopLeft => (-1, -1),
+        Anchor::Top => (0, -1),
+        Anchor::TopRight => (1, -1),
+        Anchor::Left => (-1, 0),
+        Anchor::Center => (0, 0),
+        Anchor::Right => (1, 0),
+        Anchor::BottomLeft => (-1, 1),
+        Anchor::Bottom => (0, 1),
+        Anchor::BottomRight => (1, 1),
+    };
+    (axis(col, outer.0, inner.0, offset.0), axis(row, outer.1, inner.1, offset.1))
+}
+
+pub fn watermark(img: &mut RgbaImage, mark: &RgbaImage, op: &Watermark) -> Result<()> {
+    let anchor = Anchor::from_i32(op.anchor).ok_or_else(|| anyhow!("unknown anchor {}", op.anchor))?;
+    if !(0.0..=1.0).contains(&op.scale) {
+        bail!("watermark scale {} is not between 0 and 1", op.scale);
+    }
+    if !(0.0..=1.0).contains(&op.opacity) {
+        bail!("watermark opacity {} is not between 0 and 1", op.opacity);
+    }
+    let (width, height) = img.dimensions();
+    if op.x >= width || op.y >= height {
+        match op.tile {
+            true => bail!("watermark tile gaps ({}, {}) are not smaller than the image", op.x, op.y),
+            false => bail!("watermark position ({}, {}) is outside of the image", op.x, op.y),
+        }
+    }
+
+    let mut mark = if op.scale > 0.0 {
+        let w = (width as f32 * op.scale).round().max(1.0) as u32;
+        let h = (mark.height() as f32 * w as f32 / mark.width() as f32).round().max(1.0) as u32;
+        imageops::resize(mark, w, h, imageops::FilterType::CatmullRom)
+    } else {
+        mark.clone()
+    };
+    if op.opacity > 0.0 && op.opacity < 1.0 {
+        for p in mark.pixels_mut() {
+            p[3] = (p[3] as f32 * op.opacity).round() as u8;
+        }
+    }
+
+    if op.tile {
+        let step_x = mark.width().saturating_add(op.x) as usize;
+        let step_y = mark.height().saturating_add(op.y) as usize;
+        for y in (0..height).step_by(step_y) {
+            for x in (0..width).step_by(step_x) {
+                imageops::overlay(img, &mark, x as i64, y as i64);
+            }
+        }
+    } else {
+        let (x, y) = position(anchor, (width, height), mark.dimensions(), (op.x, op.y));
+        imageops::overlay(img, &mark, x, y);
+    }
+    Ok(())
+}
+
+pub fn text(img: &mut RgbaImage, font: &Font, op: &Text) -> Result<()> {
+    let anchor = Anchor::from_i32(op.anchor).ok_or_else(|| anyhow!("unknown anchor {}", op.anchor))?;
+    if op.text.is_empty() {
+        bail!("text is empty");
+    }
+    let size = if op.size == 0.0 { DEFAULT_TEXT_SIZE } else { op.size };
+    if !(1.0..=MAX_TEXT_SIZE).contains(&size) {
+        bail!("text size {} is not between 1 and {}", op.size, MAX_TEXT_SIZE);
+    }
+    let color = op.color.as_ref().map(|c| c.to_rgba()).unwrap_or([255, 255, 255, 255]);
+
+    let scale = Scale::uniform(size);
+    let metrics = font.v_metrics(scale);
+    let glyphs: Vec<_> = font.layout(&op.text, scale, point(0.0, metrics.ascent)).collect();
+    let text_width = glyphs
+        .last()
+        .map(|g| g.position().x + g.unpositioned().h_metrics().advance_width)
+        .unwrap_or(0.0);
+    let text_size = (text_width.ceil() as u32, (metrics.ascent - metrics.descent).ceil() as u32);
+
+    let (width, height) = img.dimensions();
+    let (x, y) = position(anchor, (width, height), text_size, (op.x, op.y));
+    for glyph in &glyphs {
+        let Some(bb) = glyph.pixel_bounding_box() else {
+            continue;
+        };
+        glyph.draw(|gx, gy, coverage| {
+            let px = x + bb.min.x as i64 + gx as i64;
+            let py = y + bb.min.y as i64 + gy as i64;
+            if px >= 0 && py >= 0 && px < width as i64 && py < height as i64 {
+                blend(img.get_pixel_mut(px as u32, py as u32), color, coverage);
+            }
+        });
+    }
+    Ok(())
+}
+
+// Source-over blending of `color` at the given coverage
+fn blend(dst: &mut Rgba<u8>, color: [u8; 4], coverage: f32) {
+    let src_a = coverage * color[3] as f32 / 255.0;
+    let dst_a = dst[3] as f32 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    if out_a <= 0.0 {
+        return;
+    }
+    for i in 0..3 {
+        let v = (color[i] as f32 * src_a + dst[i] as f32 * dst_a * (1.0 - src_a)) / out_a;
+        dst[i] = v.round().clamp(0.0, 255.0) as u8;
+    }
+    dst[3] = (out_a * 255.0).round() as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    #[test]
+    fn position_should_follow_anchor() {
+        let (outer, inner, offset) = ((100, 50), (20, 10), (5, 5));
+        assert_eq!(position(Anchor::TopLeft, outer, inner, offset), (5, 5));
+        assert_eq!(position(Anchor::BottomRight, outer, inner, offset), (75, 35));
+        assert_eq!(position(Anchor::Center, outer, inner, (0, 0)), (40, 20));
+        assert_eq!(position(Anchor::Top, outer, inner, (0, 2)), (40, 2));
+    }
+
+    #[test]
+    fn watermark_should_respect_opacity_and_anchor() {
+        let mut img = RgbaImage::from_pixel(10, 10, WHITE);
+        let mark = RgbaImage::from_pixel(2, 2, RED);
+        let op = Watermark {
+            opacity: 0.5,
+            anchor: Anchor::BottomRight as i32,
+            ..Default::default()
+        };
+        watermark(&mut img, &mark, &op).unwrap();
+        assert_eq!(*img.get_pixel(0, 0), WHITE);
+        let p = img.get_pixel(9, 9);
+        // image's blending may round the alpha down by one
+        assert!(p[0] == 255 && p[3] >= 254, "blended {:?}", p);
+        assert!((126..=129).contains(&p[1]), "half blended green {}", p[1]);
+    }
+
+    #[test]
+    fn watermark_could_be_tiled_and_scaled() {
+        let mut img = RgbaImage::from_pixel(10, 10, WHITE);
+        let mark = RgbaImage::from_pixel(4, 4, RED);
+        let op = Watermark {
+            x: 3,
+            y: 3,
+            scale: 0.2,
+            tile: true,
+            ..Default::default()
+        };
+        watermark(&mut img, &mark, &op).unwrap();
+        // 2x2 tiles every 5 pixels
+        for (x, y) in [(0, 0), (5, 5), (6, 0)] {
+            assert_eq!(*img.get_pixel(x, y), RED);
+        }
+        for (x, y) in [(2, 2), (4, 9), (9, 4)] {
+            assert_eq!(*img.get_pixel(x, y), WHITE);
+        }
+    }
+
+    #[test]
+    fn invalid_watermarks_should_fail() {
+        let mut img = RgbaImage::from_pixel(10, 10, WHITE);
+        let mark = RgbaImage::from_pixel(2, 2, RED);
+        let outside = Watermark { x: 10, ..Default::default() };
+        assert!(watermark(&mut img, &mark, &outside).is_err());
+        let opacity = Watermark { opacity: 1.5, ..Default::default() };
+        assert!(watermark(&mut img, &mark, &opacity).is_err());
+    }
+
+    #[test]
+    fn blend_should_be_source_over() {
+        let mut p = Rgba([0, 0, 0, 0]);
+        blend(&mut p, [255, 0, 0, 255], 1.0);
+        assert_eq!(p, RED);
+        let mut p = WHITE;
+        blend(&mut p, [0, 0, 0, 255], 0.0);
+        assert_eq!(p, WHITE);
+    }
+}
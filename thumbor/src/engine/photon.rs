@@ -1,124 +1,264 @@
-use std::io::Cursor;
-
-use crate::pb::ImageSpec;
 use crate::pb::Spec;
-use crate::pb::spec;
 use crate::pb::{Resize, Crop, Fliph, Flipv, Contrast, Filter, Watermark};
+use crate::pb::{Rotate, Blur, Sharpen, Grayscale, Sepia, Brightness, Saturation, Hue, Padding, RoundCorners, Text};
 use crate::pb::{resize, filter};
 
+use super::fit::{self, Layout};
+use super::rgba;
 use super::{Engine, SpecTransform};
-use anyhow::Result;
+use crate::error::ThumborError;
+use crate::metadata;
+use crate::format::OutputFormat;
+use anyhow::{bail, Result};
 use bytes::Bytes;
-use image::{DynamicImage, ImageBuffer, ImageOutputFormat};
-use lazy_static::lazy_static;
+use std::time::Duration;
+use image::{ImageBuffer, RgbaImage};
 use photon_rs::{
-    effects, filters, multiple, native::open_image_from_bytes, transform, PhotonImage,
+    colour_spaces, conv, effects, filters, monochrome, native::open_image_from_bytes, transform,
+    PhotonImage,
 };
 
-lazy_static! {
-    static ref WATERMARK: PhotonImage = {
-        let data = include_bytes!("../../rust-logo.png");
-        let watermark = open_image_from_bytes(data).unwrap();
-        transform::resize(&watermark, 64, 64, transform::SamplingFilter::Nearest)
-    };
-}
-
-pub struct Photon(PhotonImage);
+pub struct Photon(PhotonImage, fit::Windows);
 
 impl TryFrom<Bytes> for Photon {
-    type Error = anyhow::Error;
+    type Error = ThumborError;
 
     fn try_from(data: Bytes) -> Result<Self, Self::Error> {
-        Ok(Self(open_image_from_bytes(&data)?))
+        let img = open_image_from_bytes(&data).map_err(|e| ThumborError::Decode(e.to_string()))?;
+        // phone cameras store pixels sideways and rely on the orientation tag
+        let img = match metadata::orientation(&data) {
+            Some(orientation) if orientation != 1 => from_rgba(metadata::orient(to_rgba(&img), orientation)),
+            _ => img,
+        };
+        Ok(Self(img, fit::Windows::default()))
+    }
+}
+
+impl From<RgbaImage> for Photon {
+    fn from(img: RgbaImage) -> Self {
+        Self(from_rgba(img), fit::Windows::default())
+    }
+}
+
+impl From<Photon> for RgbaImage {
+    fn from(engine: Photon) -> Self {
+        to_rgba(&engine.0)
     }
 }
 
 impl Engine for Photon {
-    fn apply(&mut self, specs: &[Spec]) {
-        for spec in specs.iter() {
-            match spec.data {
-                Some(spec::Data::Crop(ref v)) => self.transform(v),
-                Some(spec::Data::Contrast(ref v)) => self.transform(v),
-                Some(spec::Data::Filter(ref v)) => self.transform(v),
-                Some(spec::Data::Fliph(ref v)) => self.transform(v),
-                Some(spec::Data::Flipv(ref v)) => self.transform(v),
-                Some(spec::Data::Resize(ref v)) => self.transform(v),
-                Some(spec::Data::Watermark(ref v)) => self.transform(v),
-                _ => {}
-            }
-        }
+    fn apply(&mut self, specs: &[Spec]) -> Result<Vec<Duration>, ThumborError> {
+        super::apply_specs(self, specs)
     }
 
-    fn generate(self, format: ImageOutputFormat) -> Vec<u8> {
-        image_to_buf(self.0, format)
+    fn windows(&mut self) -> &mut fit::Windows {
+        &mut self.1
+    }
+
+    fn generate(self, format: OutputFormat) -> Result<Vec<u8>, ThumborError> {
+        rgba::encode(to_rgba(&self.0), format).map_err(|e| ThumborError::Encode(e.to_string()))
     }
 }
 
 impl SpecTransform<&Crop> for Photon {
-    fn transform(&mut self, op: &Crop) {
-        let img = transform::crop(&mut self.0, op.x1, op.y1, op.x2, op.y2);
+    fn transform(&mut self, op: &Crop) -> Result<()> {
+        let (width, height) = (self.0.get_width(), self.0.get_height());
+        if op.x1 >= op.x2 || op.y1 >= op.y2 || op.x2 > width || op.y2 > height {
+            bail!(
+                "crop area ({}, {}) - ({}, {}) is outside of the {}x{} image",
+                op.x1, op.y1, op.x2, op.y2, width, height
+            );
+        }
+        let img = transform::crop(&self.0, op.x1, op.y1, op.x2, op.y2);
         self.0 = img;
+        Ok(())
     }
 }
 
 impl SpecTransform<&Contrast> for Photon {
-    fn transform(&mut self, op: &Contrast) {
+    fn transform(&mut self, op: &Contrast) -> Result<()> {
+        if !(-255.0..=255.0).contains(&op.contrast) {
+            bail!("contrast {} is not between -255 and 255", op.contrast);
+        }
+        // photon makes every pixel opaque here, so the alpha channel is put back afterwards
+        let alpha: Vec<u8> = self.0.get_raw_pixels().into_iter().skip(3).step_by(4).collect();
         effects::adjust_contrast(&mut self.0, op.contrast);
+        let mut pixels = self.0.get_raw_pixels();
+        for (pixel, alpha) in pixels.chunks_exact_mut(4).zip(alpha) {
+            pixel[3] = alpha;
+        }
+        self.0 = PhotonImage::new(pixels, self.0.get_width(), self.0.get_height());
+        Ok(())
     }
 }
 
 impl SpecTransform<&Flipv> for Photon {
-    fn transform(&mut self, _op: &Flipv) {
-        transform::flipv(&mut self.0)
+    fn transform(&mut self, _op: &Flipv) -> Result<()> {
+        transform::flipv(&mut self.0);
+        Ok(())
     }
 }
 
 impl SpecTransform<&Fliph> for Photon {
-    fn transform(&mut self, _op: &Fliph) {
-        transform::fliph(&mut self.0)
+    fn transform(&mut self, _op: &Fliph) -> Result<()> {
+        transform::fliph(&mut self.0);
+        Ok(())
     }
 }
 
 impl SpecTransform<&Filter> for Photon {
-    fn transform(&mut self, op: &Filter) {
+    fn transform(&mut self, op: &Filter) -> Result<()> {
         match filter::Filter::from_i32(op.filter) {
-            Some(filter::Filter::Unspecified) => {}
-            Some(f) => filters::filter(&mut self.0, f.to_str().unwrap()),
-            _ => {}
+            Some(f) => {
+                if let Some(name) = f.to_str() {
+                    filters::filter(&mut self.0, name);
+                }
+                Ok(())
+            }
+            None => bail!("unknown filter {}", op.filter),
         }
     }
 }
 
 impl SpecTransform<&Resize> for Photon {
-    fn transform(&mut self, op: &Resize) {
-        let img = match resize::ResizeType::from_i32(op.rtype).unwrap() {
-            resize::ResizeType::Normal => transform::resize(
-                &self.0,
-                op.width,
-                op.height,
-                resize::SampleFilter::from_i32(op.filter).unwrap().into(),
-            ),
-            resize::ResizeType::SeamCarve => transform::seam_carve(&self.0, op.width, op.height),
+    fn transform(&mut self, op: &Resize) -> Result<()> {
+        let plan = fit::plan((self.0.get_width(), self.0.get_height()), op)?;
+        let (w, h) = plan.layout.scale();
+        let img = match plan.rtype {
+            resize::ResizeType::Normal => {
+                let img = transform::resize(&self.0, w, h, plan.filter.into());
+                match plan.layout {
+                    Layout::Scale(..) => img,
+                    _ => {
+                        let background = rgba::background(op.background.as_ref());
+                        from_rgba(fit::finish(to_rgba(&img), &plan, background, &mut self.1))
+                    }
+                }
+            }
+            resize::ResizeType::SeamCarve => transform::seam_carve(&self.0, w, h),
         };
         self.0 = img;
+        Ok(())
     }
 }
 
 impl SpecTransform<&Watermark> for Photon {
-    fn transform(&mut self, op: &Watermark) {
-        multiple::watermark(&mut self.0, &WATERMARK, op.x, op.y);
+    fn transform(&mut self, op: &Watermark) -> Result<()> {
+        let mut img = to_rgba(&self.0);
+        rgba::watermark(&mut img, op)?;
+        self.0 = from_rgba(img);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Text> for Photon {
+    fn transform(&mut self, op: &Text) -> Result<()> {
+        let mut img = to_rgba(&self.0);
+        rgba::text(&mut img, op)?;
+        self.0 = from_rgba(img);
+        Ok(())
     }
 }
 
-fn image_to_buf(img: PhotonImage, format: ImageOutputFormat) -> Vec<u8> {
-    let raw_pixels = img.get_raw_pixels();
-    let width = img.get_width();
-    let height = img.get_height();
+impl SpecTransform<&Rotate> for Photon {
+    fn transform(&mut self, op: &Rotate) -> Result<()> {
+        self.0 = from_rgba(rgba::rotate(to_rgba(&self.0), op)?);
+        Ok(())
+    }
+}
 
-    let img_buffer = ImageBuffer::from_vec(width, height, raw_pixels).unwrap();
-    let dynimage = DynamicImage::ImageRgba8(img_buffer);
+impl SpecTransform<&Blur> for Photon {
+    fn transform(&mut self, op: &Blur) -> Result<()> {
+        if op.radius == 0 || op.radius > 100 {
+            bail!("blur radius {} is not between 1 and 100", op.radius);
+        }
+        conv::gaussian_blur(&mut self.0, op.radius as i32);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Sharpen> for Photon {
+    fn transform(&mut self, op: &Sharpen) -> Result<()> {
+        self.0 = from_rgba(rgba::sharpen(&to_rgba(&self.0), op)?);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Grayscale> for Photon {
+    fn transform(&mut self, _op: &Grayscale) -> Result<()> {
+        monochrome::grayscale(&mut self.0);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Sepia> for Photon {
+    fn transform(&mut self, _op: &Sepia) -> Result<()> {
+        let mut img = to_rgba(&self.0);
+        rgba::sepia(&mut img);
+        self.0 = from_rgba(img);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Brightness> for Photon {
+    fn transform(&mut self, op: &Brightness) -> Result<()> {
+        let mut img = to_rgba(&self.0);
+        rgba::brighten(&mut img, op)?;
+        self.0 = from_rgba(img);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Saturation> for Photon {
+    fn transform(&mut self, op: &Saturation) -> Result<()> {
+        if !(-1.0..=1.0).contains(&op.saturation) {
+            bail!("saturation {} is not between -1 and 1", op.saturation);
+        }
+        if op.saturation > 0.0 {
+            colour_spaces::saturate_hsl(&mut self.0, op.saturation);
+        } else if op.saturation < 0.0 {
+            colour_spaces::desaturate_hsl(&mut self.0, -op.saturation);
+        }
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Hue> for Photon {
+    fn transform(&mut self, op: &Hue) -> Result<()> {
+        if !op.degrees.is_finite() {
+            bail!("hue rotation {} is not a number", op.degrees);
+        }
+        // photon takes the rotation as a fraction of a full turn
+        let turn = op.degrees.rem_euclid(360.0) / 360.0;
+        if turn != 0.0 {
+            colour_spaces::hue_rotate_hsl(&mut self.0, turn);
+        }
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Padding> for Photon {
+    fn transform(&mut self, op: &Padding) -> Result<()> {
+        self.0 = from_rgba(rgba::pad(&to_rgba(&self.0), op)?);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&RoundCorners> for Photon {
+    fn transform(&mut self, op: &RoundCorners) -> Result<()> {
+        let mut img = to_rgba(&self.0);
+        rgba::round_corners(&mut img, op);
+        self.0 = from_rgba(img);
+        Ok(())
+    }
+}
+
+fn to_rgba(img: &PhotonImage) -> RgbaImage {
+    ImageBuffer::from_raw(img.get_width(), img.get_height(), img.get_raw_pixels())
+        .expect("photon images are always rgba")
+}
 
-    let mut buffer = Cursor::new(Vec::with_capacity(32768));
-    dynimage.write_to(&mut buffer, format).unwrap();
-    buffer.into_inner()
+fn from_rgba(img: RgbaImage) -> PhotonImage {
+    let (width, height) = img.dimensions();
+    PhotonImage::new(img.into_raw(), width, height)
 }
@@ -0,0 +1,180 @@
+// Transforms both engines run on plain RGBA buffers, and encoding
+use std::io::Cursor;
+
+use anyhow::{anyhow, bail, Result};
+#[cfg(feature = "avif")]
+use image::{codecs::avif::AvifEncoder, ColorType, ImageEncoder};
+use image::{imageops, DynamicImage, ImageOutputFormat, Rgba, RgbaImage};
+
+use super::{check_dimensions, overlay, MAX_DIMENSION};
+use crate::assets;
+use crate::format::OutputFormat;
+use crate::pb::{Brightness, Color, Padding, Rotate, RoundCorners, Sharpen, Text, Watermark};
+
+pub fn rotate(img: RgbaImage, op: &Rotate) -> Result<RgbaImage> {
+    if !op.angle.is_finite() {
+        bail!("rotation angle {} is not a number", op.angle);
+    }
+    let rotated = match op.angle.rem_euclid(360.0) {
+        0.0 => img,
+        90.0 => imageops::rotate90(&img),
+        180.0 => imageops::rotate180(&img),
+        270.0 => imageops::rotate270(&img),
+        a => rotate_any(&img, a, background(op.background.as_ref())),
+    };
+    check_dimensions(rotated.width(), rotated.height())?;
+    Ok(rotated)
+}
+
+pub fn sharpen(img: &RgbaImage, op: &Sharpen) -> Result<RgbaImage> {
+    if !(op.sigma > 0.0 && op.sigma <= 50.0) {
+        bail!("sharpen sigma {} is not between 0 and 50", op.sigma);
+    }
+    Ok(imageops::unsharpen(img, op.sigma, op.threshold))
+}
+
+// photon's own sepia leaves the blue channel as it was
+pub fn sepia(img: &mut RgbaImage) {
+    for p in img.pixels_mut() {
+        let avg = 0.3 * p[0] as f32 + 0.59 * p[1] as f32 + 0.11 * p[2] as f32;
+        p[0] = (avg + 100.0).min(255.0) as u8;
+        p[1] = (avg + 50.0).min(255.0) as u8;
+        p[2] = avg.min(255.0) as u8;
+    }
+}
+
+// photon's own brightness skips the last pixel
+pub fn brighten(img: &mut RgbaImage, op: &Brightness) -> Result<()> {
+    if !(-255..=255).contains(&op.brightness) {
+        bail!("brightness {} is not between -255 and 255", op.brightness);
+    }
+    for p in img.pixels_mut() {
+        for i in 0..3 {
+            p[i] = (p[i] as i32 + op.brightness).clamp(0, 255) as u8;
+        }
+    }
+    Ok(())
+}
+
+pub fn pad(img: &RgbaImage, op: &Padding) -> Result<RgbaImage> {
+    let width = img.width() as u64 + op.left as u64 + op.right as u64;
+    let height = img.height() as u64 + op.top as u64 + op.bottom as u64;
+    if width > MAX_DIMENSION as u64 || height > MAX_DIMENSION as u64 {
+        bail!("padded size {}x{} is larger than {}", width, height, MAX_DIMENSION);
+    }
+
+    let mut canvas = RgbaImage::from_pixel(width as u32, height as u32, background(op.color.as_ref()));
+    imageops::overlay(&mut canvas, img, op.left as i64, op.top as i64);
+    Ok(canvas)
+}
+
+pub fn round_corners(img: &mut RgbaImage, op: &RoundCorners) {
+    let (width, height) = img.dimensions();
+    let r = op.radius.min(width / 2).min(height / 2) as f32;
+    if r == 0.0 {
+        return;
+    }
+
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+        let (Some(cx), Some(cy)) = (corner_center(px, width, r), corner_center(py, height, r)) else {
+            continue;
+        };
+        // one pixel of anti-aliasing along the rounded edge
+        let d = ((px - cx).powi(2) + (py - cy).powi(2)).sqrt();
+        let coverage = (r - d + 0.5).clamp(0.0, 1.0);
+        pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+    }
+}
+
+pub fn watermark(img: &mut RgbaImage, op: &Watermark) -> Result<()> {
+    let mark = assets::watermark(&op.name).ok_or_else(|| anyhow!("watermark {:?} is not registered", op.name))?;
+    overlay::watermark(img, &mark, op)
+}
+
+pub fn text(img: &mut RgbaImage, op: &Text) -> Result<()> {
+    let font = assets::font(&op.font).ok_or_else(|| anyhow!("font {:?} is not registered", op.font))?;
+    overlay::text(img, &font, op)
+}
+
+pub fn encode(img: RgbaImage, format: OutputFormat) -> Result<Vec<u8>> {
+    let (width, height) = img.dimensions();
+    let mut buffer = Cursor::new(Vec::with_capacity(32768));
+    match format {
+        OutputFormat::Jpeg(quality) => {
+            DynamicImage::ImageRgba8(img).write_to(&mut buffer, ImageOutputFormat::Jpeg(quality))?
+        }
+        OutputFormat::Png => DynamicImage::ImageRgba8(img).write_to(&mut buffer, ImageOutputFormat::Png)?,
+        OutputFormat::Gif => DynamicImage::ImageRgba8(img).write_to(&mut buffer, ImageOutputFormat::Gif)?,
+        // image only encodes lossless webp, libwebp does lossy
+        OutputFormat::WebP(quality) => {
+            return Ok(webp::Encoder::from_rgba(&img, width, height).encode(quality as f32).to_vec());
+        }
+        // speed 8 of 10 keeps encoding time reasonable for on-the-fly requests
+        #[cfg(feature = "avif")]
+        OutputFormat::Avif(quality) => AvifEncoder::new_with_speed_quality(&mut buffer, 8, quality)
+            .write_image(&img, width, height, ColorType::Rgba8)?,
+        // `EngineKind::run` turns these away first
+        #[cfg(not(feature = "avif"))]
+        OutputFormat::Avif(_) => bail!("avif output is not built in"),
+    }
+    Ok(buffer.into_inner())
+}
+
+// transparent when no color is given
+pub fn background(color: Option<&Color>) -> Rgba<u8> {
+    Rgba(color.map(Color::to_rgba).unwrap_or([0, 0, 0, 0]))
+}
+
+// Center of the corner circle along one axis, if the position lies in a corner band
+fn corner_center(pos: f32, len: u32, r: f32) -> Option<f32> {
+    if pos < r {
+        Some(r)
+    } else if pos > len as f32 - r {
+        Some(len as f32 - r)
+    } else {
+        None
+    }
+}
+
+// Rotate clockwise by any angle with bilinear sampling, growing the canvas to fit the corners
+fn rotate_any(img: &RgbaImage, degrees: f32, background: Rgba<u8>) -> RgbaImage {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (w, h) = (img.width() as f32, img.height() as f32);
+    // the epsilon keeps float noise (cos 90° is not exactly 0) from adding a pixel
+    let new_w = (w * cos.abs() + h * sin.abs() - 1e-3).ceil().max(1.0) as u32;
+    let new_h = (w * sin.abs() + h * cos.abs() - 1e-3).ceil().max(1.0) as u32;
+
+    let (cx, cy) = (w / 2.0, h / 2.0);
+    let (ncx, ncy) = (new_w as f32 / 2.0, new_h as f32 / 2.0);
+    RgbaImage::from_fn(new_w, new_h, |x, y| {
+        // map the destination pixel center back into the source
+        let dx = x as f32 + 0.5 - ncx;
+        let dy = y as f32 + 0.5 - ncy;
+        let sx = dx * cos + dy * sin + cx - 0.5;
+        let sy = -dx * sin + dy * cos + cy - 0.5;
+        sample_bilinear(img, sx, sy, background)
+    })
+}
+
+fn sample_bilinear(img: &RgbaImage, x: f32, y: f32, background: Rgba<u8>) -> Rgba<u8> {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let pixel = |px: f32, py: f32| -> [f32; 4] {
+        if px < 0.0 || py < 0.0 || px >= img.width() as f32 || py >= img.height() as f32 {
+            background.0.map(|v| v as f32)
+        } else {
+            img.get_pixel(px as u32, py as u32).0.map(|v| v as f32)
+        }
+    };
+    let (p00, p10) = (pixel(x0, y0), pixel(x0 + 1.0, y0));
+    let (p01, p11) = (pixel(x0, y0 + 1.0), pixel(x0 + 1.0, y0 + 1.0));
+
+    let mut out = [0u8; 4];
+    for i in 0..4 {
+        let top = p00[i] * (1.0 - fx) + p10[i] * fx;
+        let bottom = p01[i] * (1.0 - fx) + p11[i] * fx;
+        out[i] = (top * (1.0 - fy) + bottom * fy).round().clamp(0.0, 255.0) as u8;
+    }
+    Rgba(out)
+}
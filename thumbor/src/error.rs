@@ -0,0 +1,156 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::assets::AssetError;
+use crate::pool::PoolError;
+use crate::source::FetchError;
+use crate::storage::StorageError;
+
+#[derive(Debug, Error)]
+pub enum ThumborError {
+    #[error("invalid spec: {0}")]
+    InvalidSpec(String),
+    #[error("missing or invalid signature")]
+    InvalidSignature,
+    #[error("uploads are refused while no secret is configured")]
+    SecretRequired,
+    #[error(transparent)]
+    Fetch(#[from] FetchError),
+    #[error("cannot decode source image: {0}")]
+    Decode(String),
+    #[error("source image has more than {0} pixels")]
+    TooManyPixels(u64),
+    #[error("spec {index} ({name}) failed: {reason}")]
+    Spec {
+        index: usize,
+        name: &'static str,
+        reason: String,
+    },
+    #[error("cannot encode image: {0}")]
+    Encode(String),
+    #[error("{0} output is not built in")]
+    UnsupportedFormat(&'static str),
+    #[error(transparent)]
+    Asset(#[from] AssetError),
+    #[error(transparent)]
+    Pool(#[from] PoolError),
+    #[error("request took longer than {0:?}")]
+    Deadline(Duration),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spec_index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spec: Option<&'static str>,
+}
+
+impl ThumborError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ThumborError::InvalidSpec(_) => StatusCode::BAD_REQUEST,
+            ThumborError::InvalidSignature => StatusCode::FORBIDDEN,
+            ThumborError::SecretRequired => StatusCode::FORBIDDEN,
+            ThumborError::Fetch(e) => e.status(),
+            ThumborError::Decode(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ThumborError::TooManyPixels(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ThumborError::Spec { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ThumborError::Encode(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ThumborError::UnsupportedFormat(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ThumborError::Asset(e) => e.status(),
+            ThumborError::Pool(e) => e.status(),
+            ThumborError::Deadline(_) => StatusCode::GATEWAY_TIMEOUT,
+            ThumborError::Storage(e) => e.status(),
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            ThumborError::InvalidSpec(_) => "invalid_spec",
+            ThumborError::InvalidSignature => "invalid_signature",
+            ThumborError::SecretRequired => "secret_required",
+            ThumborError::Fetch(_) => "fetch_failed",
+            ThumborError::Decode(_) => "decode_failed",
+            ThumborError::TooManyPixels(_) => "too_many_pixels",
+            ThumborError::Spec { .. } => "spec_failed",
+            ThumborError::Encode(_) => "encode_failed",
+            ThumborError::UnsupportedFormat(_) => "unsupported_format",
+            ThumborError::Asset(_) => "invalid_asset",
+            ThumborError::Pool(PoolError::Full(_)) => "overloaded",
+            ThumborError::Pool(_) => "processing_failed",
+            ThumborError::Deadline(_) => "deadline_exceeded",
+            ThumborError::Storage(StorageError::NotFound(_)) => "not_found",
+            ThumborError::Storage(_) => "storage_failed",
+        }
+    }
+}
+
+impl IntoResponse for ThumborError {
+    fn into_response(self) -> Response {
+        let (spec_index, spec) = match &self {
+            ThumborError::Spec { index, name, .. } => (Some(*index), Some(*name)),
+            _ => (None, None),
+        };
+        let body = ErrorBody {
+            error: self.code(),
+            message: self.to_string(),
+            spec_index,
+            spec,
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_error_identifies_failing_spec() {
+        let err = ThumborError::Spec {
+            index: 2,
+            name: "crop",
+            reason: "crop area is outside of the image".into(),
+        };
+        assert_eq!(err.to_string(), "spec 2 (crop) failed: crop area is outside of the image");
+        assert_eq!(err.into_response().status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn fetch_error_keeps_its_status() {
+        let err: ThumborError = FetchError::TooLarge(10).into();
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn large_sources_are_not_unsupported() {
+        let err = ThumborError::TooManyPixels(100);
+        assert_eq!((err.status(), err.code()), (StatusCode::PAYLOAD_TOO_LARGE, "too_many_pixels"));
+        assert_eq!(err.to_string(), "source image has more than 100 pixels");
+    }
+
+    #[test]
+    fn missing_secret_is_forbidden() {
+        let err = ThumborError::SecretRequired;
+        assert_eq!((err.status(), err.code()), (StatusCode::FORBIDDEN, "secret_required"));
+    }
+
+    #[test]
+    fn overload_is_retryable() {
+        let err: ThumborError = PoolError::Full(64).into();
+        assert_eq!((err.status(), err.code()), (StatusCode::SERVICE_UNAVAILABLE, "overloaded"));
+        let err = ThumborError::Deadline(Duration::from_secs(30));
+        assert_eq!((err.status(), err.code()), (StatusCode::GATEWAY_TIMEOUT, "deadline_exceeded"));
+    }
+}
@@ -0,0 +1,187 @@
+use image::ImageFormat;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::engine::is_animated;
+use crate::pb::{output, Output};
+
+// AVIF is only encoded when built with the `avif` feature
+pub const HAS_AVIF: bool = cfg!(feature = "avif");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    Jpeg(u8),
+    Png,
+    WebP(u8),
+    Avif(u8),
+    Gif,
+}
+
+impl OutputFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg(_) => "image/jpeg",
+            OutputFormat::Png => "image/png",
+            OutputFormat::WebP(_) => "image/webp",
+            OutputFormat::Avif(_) => "image/avif",
+            OutputFormat::Gif => "image/gif",
+        }
+    }
+
+    // formats animated sources are encoded to frame by frame, others get the first frame
+    pub fn can_animate(&self) -> bool {
+        matches!(self, OutputFormat::WebP(_) | OutputFormat::Gif)
+    }
+}
+
+// What a spec without an output format or quality gets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputDefaults {
+    #[serde(deserialize_with = "deserialize_format")]
+    pub format: output::Format,
+    pub jpeg_quality: u8,
+    pub webp_quality: u8,
+    pub avif_quality: u8,
+}
+
+impl Default for OutputDefaults {
+    fn default() -> Self {
+        Self {
+            format: output::Format::Auto,
+            jpeg_quality: 85,
+            webp_quality: 80,
+            avif_quality: 70,
+        }
+    }
+}
+
+// `auto`, `jpeg`, `png`, `webp`, `avif` or `gif`
+pub fn parse_format(name: &str) -> Result<output::Format, String> {
+    output::Format::from_str_name(&name.to_uppercase()).ok_or_else(|| format!("unknown output format {:?}", name))
+}
+
+fn deserialize_format<'de, D: Deserializer<'de>>(deserializer: D) -> Result<output::Format, D::Error> {
+    parse_format(&String::deserialize(deserializer)?).map_err(de::Error::custom)
+}
+
+// Pick the output format: an explicit format in the spec wins, then the configured default,
+// otherwise the best format the client accepts, falling back to PNG for sources that may
+// carry transparency. Animated sources stay animated: WebP when accepted, GIF otherwise
+pub fn negotiate(output: Option<&Output>, accept: &str, source: &[u8], defaults: &OutputDefaults) -> OutputFormat {
+    let format = match output.and_then(|o| output::Format::from_i32(o.format)) {
+        None | Some(output::Format::Auto) => defaults.format,
+        Some(format) => format,
+    };
+    let quality = output.map(|o| o.quality).unwrap_or(0);
+    let quality = |default: u8| match quality {
+        0 => default,
+        q => q.min(100) as u8,
+    };
+    let jpeg = OutputFormat::Jpeg(quality(defaults.jpeg_quality));
+    let webp = OutputFormat::WebP(quality(defaults.webp_quality));
+    let avif = OutputFormat::Avif(quality(defaults.avif_quality));
+
+    match format {
+        output::Format::Jpeg => jpeg,
+        output::Format::Png => OutputFormat::Png,
+        output::Format::Webp => webp,
+        output::Format::Avif => avif,
+        output::Format::Gif => OutputFormat::Gif,
+        output::Format::Auto if is_animated(source) => match accepts(accept, "image/webp") {
+            true => webp,
+            false => OutputFormat::Gif,
+        },
+        output::Format::Auto if HAS_AVIF && accepts(accept, "image/avif") => avif,
+        output::Format::Auto if accepts(accept, "image/webp") => webp,
+        output::Format::Auto => match image::guess_format(source) {
+            Ok(ImageFormat::Png) | Ok(ImageFormat::Gif) | Ok(ImageFormat::WebP) => OutputFormat::Png,
+            _ => jpeg,
+        },
+    }
+}
+
+// Whether the Accept header lists the media type explicitly with a non-zero q value
+pub fn accepts(accept: &str, mime: &str) -> bool {
+    accept.split(',').any(|item| {
+        let mut parts = item.split(';').map(str::trim);
+        let matched = parts.next().is_some_and(|t| t.eq_ignore_ascii_case(mime));
+        let rejected = parts.any(|p| {
+            p.strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .is_some_and(|q| q == 0.0)
+        });
+        matched && !rejected
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = include_bytes!("../rust-logo.png");
+    const BROWSER: &str = "image/avif,image/webp,image/apng,image/*,*/*;q=0.8";
+    const DEFAULTS: OutputDefaults = OutputDefaults {
+        format: output::Format::Auto,
+        jpeg_quality: 85,
+        webp_quality: 80,
+        avif_quality: 70,
+    };
+
+    #[test]
+    fn explicit_format_wins() {
+        let output = Output {
+            format: output::Format::Jpeg as i32,
+            quality: 60,
+            ..Default::default()
+        };
+        assert_eq!(negotiate(Some(&output), BROWSER, PNG, &DEFAULTS), OutputFormat::Jpeg(60));
+    }
+
+    #[test]
+    fn auto_format_follows_accept_header() {
+        let best = if HAS_AVIF { OutputFormat::Avif(70) } else { OutputFormat::WebP(80) };
+        assert_eq!(negotiate(None, BROWSER, PNG, &DEFAULTS), best);
+        assert_eq!(negotiate(None, "image/webp,*/*", PNG, &DEFAULTS), OutputFormat::WebP(80));
+        assert_eq!(negotiate(None, "image/avif;q=0,image/webp", PNG, &DEFAULTS), OutputFormat::WebP(80));
+    }
+
+    #[test]
+    fn auto_format_keeps_transparency() {
+        assert_eq!(negotiate(None, "*/*", PNG, &DEFAULTS), OutputFormat::Png);
+        assert_eq!(negotiate(None, "*/*", &[0xff, 0xd8, 0xff, 0xe0], &DEFAULTS), OutputFormat::Jpeg(85));
+    }
+
+    #[test]
+    fn configured_defaults_apply_to_auto() {
+        let defaults = OutputDefaults {
+            format: parse_format("webp").unwrap(),
+            webp_quality: 50,
+            ..Default::default()
+        };
+        assert_eq!(negotiate(None, BROWSER, PNG, &defaults), OutputFormat::WebP(50));
+        let png = Output {
+            format: output::Format::Png as i32,
+            ..Default::default()
+        };
+        assert_eq!(negotiate(Some(&png), BROWSER, PNG, &defaults), OutputFormat::Png);
+        assert!(parse_format("bmp").is_err());
+    }
+
+    #[test]
+    fn auto_format_keeps_animations() {
+        use image::codecs::gif::GifEncoder;
+        use image::{Frame, Rgba, RgbaImage};
+
+        let frames = [[255, 0, 0, 255], [0, 0, 255, 255]].map(|c| Frame::new(RgbaImage::from_pixel(4, 4, Rgba(c))));
+        let mut gif = vec![];
+        GifEncoder::new(&mut gif).encode_frames(frames).unwrap();
+
+        assert_eq!(negotiate(None, BROWSER, &gif, &DEFAULTS), OutputFormat::WebP(80));
+        assert_eq!(negotiate(None, "image/avif,*/*", &gif, &DEFAULTS), OutputFormat::Gif);
+        let avif = Output {
+            format: output::Format::Avif as i32,
+            ..Default::default()
+        };
+        assert_eq!(negotiate(Some(&avif), BROWSER, &gif, &DEFAULTS), OutputFormat::Avif(70));
+    }
+}
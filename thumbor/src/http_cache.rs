@@ -0,0 +1,91 @@
+use axum::http::{header, HeaderMap, HeaderValue};
+use sha2::{Digest, Sha256};
+
+use crate::engine::EngineKind;
+use crate::format::{accepts, OutputDefaults};
+
+// Strong validator of a processed image: the same source, spec and engine give the same bytes once
+// the output format is picked. It is known before the source is loaded, so it covers what the
+// format is picked from instead of the format: the formats the client accepts and the defaults
+pub fn etag(url: &str, spec: &str, engine: EngineKind, accept: &str, output: &OutputDefaults) -> String {
+    let accepted = [accepts(accept, "image/avif"), accepts(accept, "image/webp")];
+    let digest = Sha256::new()
+        .chain_update(url)
+        .chain_update([0])
+        .chain_update(spec)
+        .chain_update([0])
+        .chain_update(format!("{:?} {:?} {:?}", engine, accepted, output))
+        .finalize();
+    format!("\"{:x}\"", digest)
+}
+
+// If-None-Match holds `*` or a list of tags, compared weakly (RFC 9110 13.1.2)
+pub fn not_modified(request: &HeaderMap, etag: &str) -> bool {
+    request
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+// Validators and freshness shared by 200 and 304 responses
+pub fn caching(etag: &str, last_modified: Option<&str>, max_age: u64) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let cache_control = format!("public, max-age={}", max_age);
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_str(&cache_control).unwrap());
+    headers.insert(header::VARY, HeaderValue::from_static("accept"));
+    if let Ok(etag) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, etag);
+    }
+    // passed through as the origin sent it, dropped if it is not a valid header value
+    if let Some(Ok(v)) = last_modified.map(HeaderValue::from_str) {
+        headers.insert(header::LAST_MODIFIED, v);
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(if_none_match: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_str(if_none_match).unwrap());
+        headers
+    }
+
+    #[test]
+    fn etag_should_depend_on_every_part() {
+        let output = OutputDefaults::default();
+        let tag = etag("http://a/b.png", "resize:10x10", EngineKind::Photon, "image/webp", &output);
+        assert!(tag.starts_with('"') && tag.ends_with('"') && tag.len() == 66);
+        assert_eq!(tag, etag("http://a/b.png", "resize:10x10", EngineKind::Photon, "image/webp,*/*", &output));
+        assert_ne!(tag, etag("http://a/c.png", "resize:10x10", EngineKind::Photon, "image/webp", &output));
+        assert_ne!(tag, etag("http://a/b.png", "resize:20x10", EngineKind::Photon, "image/webp", &output));
+        assert_ne!(tag, etag("http://a/b.png", "resize:10x10", EngineKind::ImageRs, "image/webp", &output));
+        assert_ne!(tag, etag("http://a/b.png", "resize:10x10", EngineKind::Photon, "image/png", &output));
+        let output = OutputDefaults { webp_quality: 50, ..output };
+        assert_ne!(tag, etag("http://a/b.png", "resize:10x10", EngineKind::Photon, "image/webp", &output));
+    }
+
+    #[test]
+    fn if_none_match_should_be_compared_weakly() {
+        let tag = "\"abc\"";
+        assert!(not_modified(&request("\"abc\""), tag));
+        assert!(not_modified(&request("\"x\", W/\"abc\""), tag));
+        assert!(not_modified(&request("*"), tag));
+        assert!(!not_modified(&request("\"abcd\""), tag));
+        assert!(!not_modified(&HeaderMap::new(), tag));
+    }
+
+    #[test]
+    fn caching_headers_should_be_set() {
+        let headers = caching("\"abc\"", Some("Wed, 21 Oct 2015 07:28:00 GMT"), 60);
+        assert_eq!(headers[header::CACHE_CONTROL], "public, max-age=60");
+        assert_eq!(headers[header::ETAG], "\"abc\"");
+        assert_eq!(headers[header::LAST_MODIFIED], "Wed, 21 Oct 2015 07:28:00 GMT");
+        assert!(caching("\"abc\"", Some("bad\nvalue"), 60).get(header::LAST_MODIFIED).is_none());
+    }
+}
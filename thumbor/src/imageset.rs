@@ -0,0 +1,237 @@
+use anyhow::{bail, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::Serialize;
+
+use crate::format::{parse_format, HAS_AVIF};
+use crate::pb::{output, resize, ImageSpec, Spec};
+use crate::signature::{image_path, UrlSigner};
+
+const MAX_WIDTHS: usize = 10;
+const MAX_FORMATS: usize = 4;
+// same bound the engines put on output sizes
+const MAX_WIDTH: u32 = 8192;
+
+/// Every width of a source, once per requested format
+#[derive(Debug, Serialize)]
+pub struct ImageSet {
+    pub sources: Vec<SetSource>,
+    // the last format's srcset, for the fallback `<img>`
+    pub srcset: String,
+}
+
+/// One `<source>` of a `<picture>`
+#[derive(Debug, Serialize)]
+pub struct SetSource {
+    pub format: String,
+    // absent for auto, which is negotiated per request
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<&'static str>,
+    pub srcset: String,
+    pub images: Vec<SetImage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SetImage {
+    pub width: u32,
+    pub url: String,
+    #[serde(skip)]
+    pub spec: ImageSpec,
+}
+
+// `320,640,1280`: distinct widths, smallest first
+pub fn parse_widths(s: &str) -> Result<Vec<u32>> {
+    let mut widths = s
+        .split(',')
+        .map(|w| match w.trim().parse::<u32>() {
+            Ok(w) if (1..=MAX_WIDTH).contains(&w) => Ok(w),
+            _ => bail!("width {:?} is not between 1 and {}", w, MAX_WIDTH),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    widths.sort_unstable();
+    widths.dedup();
+    if widths.len() > MAX_WIDTHS {
+        bail!("at most {} widths can be requested", MAX_WIDTHS);
+    }
+    Ok(widths)
+}
+
+// `avif,webp,jpeg`: in order of preference, auto when none are given
+pub fn parse_formats(s: Option<&str>) -> Result<Vec<output::Format>> {
+    let mut formats = Vec::new();
+    for name in s.unwrap_or("auto").split(',') {
+        let format = parse_format(name.trim()).map_err(anyhow::Error::msg)?;
+        if format == output::Format::Avif && !HAS_AVIF {
+            bail!("avif output is not built in");
+        }
+        if !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+    if formats.len() > MAX_FORMATS {
+        bail!("at most {} formats can be requested", MAX_FORMATS);
+    }
+    Ok(formats)
+}
+
+impl ImageSet {
+    // Each image is the base spec followed by a resize to the width, keeping the aspect ratio
+    pub fn new(base: &ImageSpec, url: &str, widths: &[u32], formats: &[output::Format], signer: Option<&UrlSigner>) -> Self {
+        let quality = base.output.as_ref().map(|o| o.quality).unwrap_or(0);
+        let sources: Vec<SetSource> = formats
+            .iter()
+            .map(|&format| {
+                let images: Vec<SetImage> = widths
+                    .iter()
+                    .map(|&width| {
+                        let mut spec = base.clone().with_output(format, quality);
+                        spec.specs.push(Spec::new_resize(width, 0, resize::SampleFilter::CatmullRom));
+                        let readable = spec.to_string();
+                        let url = match signer {
+                            Some(signer) => signer.signed_path(&readable, url),
+                            None => image_path(&readable, url),
+                        };
+                        SetImage { width, url, spec }
+                    })
+                    .collect();
+                SetSource {
+                    format: format.as_str_name().to_lowercase(),
+                    content_type: content_type(format),
+                    srcset: srcset(&images),
+                    images,
+                }
+            })
+            .collect();
+        let srcset = sources.last().map(|s| s.srcset.clone()).unwrap_or_default();
+        Self { sources, srcset }
+    }
+}
+
+fn content_type(format: output::Format) -> Option<&'static str> {
+    match format {
+        output::Format::Auto => None,
+        output::Format::Jpeg => Some("image/jpeg"),
+        output::Format::Png => Some("image/png"),
+        output::Format::Webp => Some("image/webp"),
+        output::Format::Avif => Some("image/avif"),
+        output::Format::Gif => Some("image/gif"),
+    }
+}
+
+fn srcset(images: &[SetImage]) -> String {
+    images
+        .iter()
+        .map(|image| format!("{} {}w", image.url, image.width))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A `multipart/mixed` body, one part per rendered image
+pub struct Multipart {
+    boundary: String,
+    body: BytesMut,
+}
+
+// the boundary must not occur in any part, a random one makes that practically certain
+impl Default for Multipart {
+    fn default() -> Self {
+        Self {
+            boundary: format!("thumbor-{:016x}", fastrand::u64(..)),
+            body: BytesMut::new(),
+        }
+    }
+}
+
+impl Multipart {
+    pub fn content_type(&self) -> String {
+        format!("multipart/mixed; boundary={}", self.boundary)
+    }
+
+    pub fn part(&mut self, content_type: &str, location: &str, data: &[u8]) {
+        let head = format!(
+            "--{}\r\nContent-Type: {}\r\nContent-Location: {}\r\nContent-Length: {}\r\n\r\n",
+            self.boundary,
+            content_type,
+            location,
+            data.len()
+        );
+        self.body.put(head.as_bytes());
+        self.body.put(data);
+        self.body.put(&b"\r\n"[..]);
+    }
+
+    pub fn finish(mut self) -> Bytes {
+        self.body.put(format!("--{}--\r\n", self.boundary).as_bytes());
+        self.body.freeze()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::spec;
+
+    const URL: &str = "https://example.com/a.jpg";
+
+    #[test]
+    fn widths_and_formats_should_be_validated() {
+        assert_eq!(parse_widths("640, 320,640").unwrap(), vec![320, 640]);
+        assert!(parse_widths("0").is_err());
+        assert!(parse_widths("9000").is_err());
+        assert!(parse_widths("320,abc").is_err());
+        assert!(parse_widths("1,2,3,4,5,6,7,8,9,10,11").is_err());
+
+        use output::Format;
+        assert_eq!(parse_formats(None).unwrap(), vec![Format::Auto]);
+        assert_eq!(parse_formats(Some("webp,jpeg,webp")).unwrap(), vec![Format::Webp, Format::Jpeg]);
+        assert!(parse_formats(Some("bmp")).is_err());
+    }
+
+    #[test]
+    fn set_should_resize_the_base_spec_per_width() {
+        let base = ImageSpec::try_from("blur:2,format:auto:quality=60").unwrap();
+        let formats = [output::Format::Webp, output::Format::Jpeg];
+        let set = ImageSet::new(&base, URL, &[320, 640], &formats, None);
+
+        assert_eq!(set.sources.len(), 2);
+        assert_eq!(set.sources[0].content_type, Some("image/webp"));
+        let image = &set.sources[1].images[1];
+        assert_eq!(image.width, 640);
+        assert_eq!(image.spec.specs.len(), 2);
+        assert!(matches!(&image.spec.specs[1].data, Some(spec::Data::Resize(r)) if r.width == 640 && r.height == 0));
+        let out = image.spec.output.as_ref().unwrap();
+        assert_eq!((out.format, out.quality), (output::Format::Jpeg as i32, 60));
+
+        // urls round trip to the same spec
+        let path = image.url.strip_prefix("/image/").unwrap();
+        let spec = percent_encoding::percent_decode_str(path.split('/').next().unwrap()).decode_utf8().unwrap();
+        assert_eq!(ImageSpec::try_from(&*spec).unwrap(), image.spec);
+
+        let urls: Vec<_> = set.sources[1].images.iter().map(|i| i.url.as_str()).collect();
+        assert_eq!(set.srcset, format!("{} 320w, {} 640w", urls[0], urls[1]));
+    }
+
+    #[test]
+    fn set_urls_should_be_signed_with_a_secret() {
+        let signer = UrlSigner::new("secret");
+        let set = ImageSet::new(&ImageSpec::new(vec![]), URL, &[100], &[output::Format::Auto], Some(&signer));
+        let image = &set.sources[0].images[0];
+        assert!(image.url.contains("?s="));
+        assert!(signer.verify(&image.spec.to_string(), URL, image.url.rsplit("?s=").next().unwrap()));
+        assert_eq!(set.sources[0].content_type, None);
+        assert_eq!(set.sources[0].format, "auto");
+    }
+
+    #[test]
+    fn multipart_should_frame_every_part() {
+        let mut multipart = Multipart::default();
+        multipart.part("image/png", "/image/a/b", b"png");
+        multipart.part("image/jpeg", "/image/c/d", b"jpeg");
+        let boundary = multipart.boundary.clone();
+        let body = multipart.finish();
+        let body = std::str::from_utf8(&body).unwrap();
+
+        assert_eq!(body.matches(&format!("--{}\r\n", boundary)).count(), 2);
+        assert!(body.ends_with(&format!("\r\n--{}--\r\n", boundary)));
+        assert!(body.contains("Content-Type: image/jpeg\r\nContent-Location: /image/c/d\r\nContent-Length: 4\r\n\r\njpeg\r\n"));
+    }
+}
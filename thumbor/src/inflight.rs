@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OnceCell;
+
+/// Lets concurrent calls for the same key share a single run of the work
+pub struct Inflight<V> {
+    // a std mutex is enough, it is never held across an await
+    calls: Mutex<HashMap<u64, Arc<OnceCell<V>>>>,
+}
+
+impl<V> Default for Inflight<V> {
+    fn default() -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<V: Clone> Inflight<V> {
+    // If the caller running `f` goes away, one of the waiting callers runs its own `f` instead
+    pub async fn run<F, Fut>(&self, key: u64, f: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let call = self.calls.lock().unwrap().entry(key).or_default().clone();
+        let value = call.get_or_init(f).await.clone();
+
+        // the first caller back removes the call, so later ones start afresh
+        let mut calls = self.calls.lock().unwrap();
+        if calls.get(&key).is_some_and(|c| Arc::ptr_eq(c, &call)) {
+            calls.remove(&key);
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn concurrent_calls_should_share_one_run() {
+        let inflight = Arc::new(Inflight::default());
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let calls: Vec<_> = (0..8)
+            .map(|_| {
+                let (inflight, runs) = (inflight.clone(), runs.clone());
+                tokio::spawn(async move {
+                    inflight
+                        .run(1, || async {
+                            tokio::time::sleep(Duration::from_millis(20)).await;
+                            runs.fetch_add(1, Ordering::SeqCst)
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for call in calls {
+            assert_eq!(call.await.unwrap(), 0);
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // finished calls are forgotten
+        assert_eq!(inflight.run(1, || async { runs.fetch_add(1, Ordering::SeqCst) }).await, 1);
+        assert!(inflight.calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn waiting_call_should_take_over_an_abandoned_run() {
+        let inflight = Inflight::default();
+        let abandoned = inflight.run(1, std::future::pending::<u32>);
+        assert!(tokio::time::timeout(Duration::from_millis(10), abandoned).await.is_err());
+        assert_eq!(inflight.run(1, || async { 5 }).await, 5);
+    }
+}
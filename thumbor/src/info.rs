@@ -0,0 +1,122 @@
+use image::{imageops, DynamicImage};
+use serde::Serialize;
+
+use crate::error::ThumborError;
+use crate::metadata;
+
+// the dominant color is picked from a thumbnail of at most this size
+const DOMINANT_SAMPLE_SIZE: u32 = 64;
+
+#[derive(Debug, Serialize)]
+pub struct ImageInfo {
+    pub width: u32,
+    pub height: u32,
+    pub format: String,
+    pub color_type: String,
+    // 1 to 8 as in EXIF, 1 when the image carries no orientation
+    pub orientation: u32,
+    pub size: usize,
+    pub dominant_color: String,
+}
+
+pub fn inspect(data: &[u8]) -> Result<ImageInfo, ThumborError> {
+    let format = image::guess_format(data).map_err(|e| ThumborError::Decode(e.to_string()))?;
+    let img = image::load_from_memory_with_format(data, format).map_err(|e| ThumborError::Decode(e.to_string()))?;
+
+    Ok(ImageInfo {
+        width: img.width(),
+        height: img.height(),
+        format: format!("{:?}", format).to_lowercase(),
+        color_type: format!("{:?}", img.color()).to_lowercase(),
+        orientation: metadata::orientation(data).unwrap_or(1),
+        size: data.len(),
+        dominant_color: dominant_color(&img),
+    })
+}
+
+// The average of the most common color bucket (4 bits per channel), ignoring transparent pixels
+fn dominant_color(img: &DynamicImage) -> String {
+    let thumbnail = imageops::thumbnail(
+        &img.to_rgba8(),
+        img.width().min(DOMINANT_SAMPLE_SIZE),
+        img.height().min(DOMINANT_SAMPLE_SIZE),
+    );
+
+    let mut buckets = vec![(0u32, [0u32; 3]); 4096];
+    for p in thumbnail.pixels().filter(|p| p[3] >= 128) {
+        let index = (p[0] as usize >> 4) << 8 | (p[1] as usize >> 4) << 4 | p[2] as usize >> 4;
+        let (count, sum) = &mut buckets[index];
+        *count += 1;
+        for i in 0..3 {
+            sum[i] += p[i] as u32;
+        }
+    }
+
+    match buckets.iter().max_by_key(|(count, _)| *count) {
+        Some((count, sum)) if *count > 0 => {
+            let [r, g, b] = sum.map(|v| v / count);
+            format!("#{:02x}{:02x}{:02x}", r, g, b)
+        }
+        _ => "#000000".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::tests::tiff;
+    use image::{ImageOutputFormat, Rgb, RgbImage};
+    use std::io::Cursor;
+
+    fn jpeg(img: RgbImage) -> Vec<u8> {
+        let mut buf = Cursor::new(vec![]);
+        DynamicImage::ImageRgb8(img)
+            .write_to(&mut buf, ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        buf.into_inner()
+    }
+
+    // an APP1 segment right after SOI
+    fn with_orientation(jpeg: &[u8], orientation: u16) -> Vec<u8> {
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend_from_slice(&tiff(orientation));
+
+        let mut data = jpeg[..2].to_vec();
+        data.extend_from_slice(&[0xff, 0xe1]);
+        data.extend_from_slice(&(app1.len() as u16 + 2).to_be_bytes());
+        data.extend_from_slice(&app1);
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    #[test]
+    fn png_info_should_work() {
+        let data = include_bytes!("../rust-logo.png");
+        let info = inspect(data).unwrap();
+        assert_eq!((info.format.as_str(), info.color_type.as_str()), ("png", "la8"));
+        assert!(info.width > 0 && info.height > 0);
+        assert_eq!(info.orientation, 1);
+        assert_eq!(info.size, data.len());
+    }
+
+    #[test]
+    fn exif_orientation_should_be_read() {
+        let data = with_orientation(&jpeg(RgbImage::new(4, 2)), 6);
+        assert_eq!(metadata::orientation(&data), Some(6));
+        let info = inspect(&data).unwrap();
+        assert_eq!((info.format.as_str(), info.orientation), ("jpeg", 6));
+    }
+
+    #[test]
+    fn dominant_color_should_be_the_most_common() {
+        let img = RgbImage::from_fn(10, 10, |x, _| if x < 7 { Rgb([200, 30, 30]) } else { Rgb([0, 0, 255]) });
+        let info = inspect(&jpeg(img)).unwrap();
+        let [r, g, b] = [1, 3, 5].map(|i| u8::from_str_radix(&info.dominant_color[i..i + 2], 16).unwrap());
+        assert!(r > 180 && g < 60 && b < 60, "{}", info.dominant_color);
+    }
+
+    #[test]
+    fn garbage_should_not_decode() {
+        assert!(matches!(inspect(b"not an image"), Err(ThumborError::Decode(_))));
+    }
+}
@@ -1,46 +1,185 @@
 use anyhow::Result;
-use axum::http::{HeaderMap, HeaderValue};
-use axum::{extract::Path, routing::get, http::StatusCode, Router, Extension};
-use image::ImageOutputFormat;
-use serde::Deserialize;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::http::{Request, StatusCode};
+use axum::{extract::{DefaultBodyLimit, MatchedPath, Path, Query}, response::{IntoResponse, Response}, routing::{get, post, put}, Json, Router, Extension};
+use axum::middleware::{self, Next};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 use tower::ServiceBuilder;
 use std::convert::TryInto;
+use std::future::Future;
 use std::num::NonZeroUsize;
 use std::sync::Arc;
-use std::hash::{Hasher, Hash};
-use std::collections::hash_map::DefaultHasher;
-use percent_encoding::{percent_decode_str, percent_encode, AsciiSet, NON_ALPHANUMERIC, CONTROLS};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use percent_encoding::percent_decode_str;
 use bytes::Bytes;
 use lru::LruCache;
-use tracing::{info, instrument};
+use tracing::{info, instrument, warn};
 
 mod pb;
-use pb::{Filter, Resize, Spec, ImageSpec};
-use pb::{resize, filter};
+use pb::ImageSpec;
 
 mod engine;
-use engine::Photon;
-use engine::{Engine, SpecTransform};
+
+mod format;
+use format::OutputFormat;
+
+mod cache;
+use cache::{cache_key, DiskCache, SizedCache};
+
+mod signature;
+use signature::UrlSigner;
+
+mod source;
+use source::{FetchError, Source, SourcePolicy};
+
+mod error;
+use error::ThumborError;
+
+mod assets;
+
+mod info;
+use info::ImageInfo;
+
+mod metadata;
+
+mod pool;
+use pool::{Pool, PoolError};
+
+mod inflight;
+use inflight::Inflight;
+
+mod http_cache;
+
+mod storage;
+use storage::{content_id, FsStorage, Storage, LOCAL_PREFIX};
+
+mod config;
+use config::{Cli, Command, Config};
+
+mod metrics;
+use metrics::METRICS;
+
+mod imageset;
+use imageset::{ImageSet, Multipart};
 
 #[derive(Deserialize)]
 struct Params {
     spec: String,
     url: String,
 }
-type Cache = Arc<Mutex<LruCache<u64, Bytes>>>;
+
+#[derive(Deserialize)]
+struct SignatureQuery {
+    s: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SetQuery {
+    // widths, comma separated
+    w: String,
+    // formats, comma separated
+    f: Option<String>,
+    s: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Uploaded {
+    id: String,
+    // the source to put in image and info urls
+    url: String,
+}
+
+type Cache = Arc<Mutex<LruCache<u64, Source>>>;
+type ResultCache = Arc<Mutex<SizedCache>>;
+type SourceDiskCache = Arc<DiskCache>;
+type Signer = Option<Arc<UrlSigner>>;
+type Policy = Arc<SourcePolicy>;
+type Workers = Arc<Pool>;
+type Fetches = Arc<Inflight<Result<Source, FetchError>>>;
+type Store = Arc<dyn Storage>;
+type Settings = Arc<Config>;
 
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
-    
-    let cache: Cache = Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(100).unwrap())));
+
+    let cli = Cli::parse();
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let signer: Signer = config
+        .secret
+        .as_ref()
+        .map(|secret| Arc::new(UrlSigner::new(secret.clone())));
+
+    if let Some(Command::Sign { spec, url }) = &cli.command {
+        match signer {
+            Some(signer) => println!("{}", signer.signed_path(spec, url)),
+            None => {
+                eprintln!("a secret must be configured to sign urls");
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+    if signer.is_none() {
+        warn!("No secret is configured, serving unsigned urls and refusing uploads");
+    }
+
+    info!("Processing images with the {:?} engine", config.engine);
+
+    let (watermarks, fonts) = assets::load_dir(&config.assets_dir);
+    info!("Loaded {} watermarks and {} fonts from {}", watermarks, fonts, config.assets_dir.display());
+
+    let sources = NonZeroUsize::new(config.cache.sources).unwrap();
+    let cache: Cache = Arc::new(Mutex::new(LruCache::new(sources)));
+    let result_cache: ResultCache = Arc::new(Mutex::new(SizedCache::new(config.cache.results)));
+    let disk_cache = DiskCache::open(&config.cache.disk_dir, config.cache.disk_size).await.unwrap();
+    let disk_cache: SourceDiskCache = Arc::new(disk_cache);
+    let policy: Policy = Arc::new(config.policy());
+    let pool: Workers = Arc::new(Pool::new(config.limits.workers(), config.limits.queue));
+    let fetches: Fetches = Arc::new(Inflight::default());
+    let storage: Store = Arc::new(FsStorage::open(&config.storage_dir).await.unwrap());
+    let addr = config.listen;
+    let max_upload_size = config.limits.max_upload_size;
+    let settings: Settings = Arc::new(config);
 
     let app = Router::new()
         .route("/image/:spec/:url", get(generate))
-        .layer(ServiceBuilder::new().layer(Extension(cache)));
+        .route("/imageset/:spec/:url", get(image_set))
+        .route("/info/:url", get(image_info))
+        .route("/watermarks/:name", put(upload_watermark))
+        .route(
+            "/upload",
+            post(upload).layer(DefaultBodyLimit::max(max_upload_size)),
+        )
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        // a layer, not a route layer, so requests no route matched are counted too
+        .layer(middleware::from_fn(count_requests))
+        .layer(
+            ServiceBuilder::new()
+                .layer(Extension(cache))
+                .layer(Extension(disk_cache))
+                .layer(Extension(result_cache))
+                .layer(Extension(signer))
+                .layer(Extension(policy))
+                .layer(Extension(settings))
+                .layer(Extension(pool))
+                .layer(Extension(fetches))
+                .layer(Extension(storage)),
+        );
 
-    let addr = "127.0.0.1:3000".parse().unwrap();
     info!("listening on {}", addr);
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
@@ -48,58 +187,401 @@ async fn main() {
         .unwrap();
 }
 
-async fn generate(Path(Params { spec, url }): Path<Params>, Extension(cache): Extension<Cache>) 
-    -> Result<(HeaderMap, Vec<u8>), StatusCode> {
+// axum hands every piece of shared state over as its own extractor
+#[allow(clippy::too_many_arguments)]
+async fn generate(
+    Path(Params { spec, url }): Path<Params>,
+    Query(SignatureQuery { s: signature }): Query<SignatureQuery>,
+    Extension(signer): Extension<Signer>,
+    Extension(policy): Extension<Policy>,
+    Extension(cache): Extension<Cache>,
+    Extension(disk_cache): Extension<SourceDiskCache>,
+    Extension(result_cache): Extension<ResultCache>,
+    Extension(settings): Extension<Settings>,
+    Extension(pool): Extension<Workers>,
+    Extension(fetches): Extension<Fetches>,
+    Extension(storage): Extension<Store>,
+    headers: HeaderMap,
+) -> Result<Response, ThumborError> {
+    let deadline = Deadline::after(settings.limits.deadline());
+    let url: &str = &percent_decode_str(&url).decode_utf8_lossy();
+
+    if let Some(signer) = signer {
+        match signature {
+            Some(s) if signer.verify(&spec, url, &s) => {}
+            _ => return Err(ThumborError::InvalidSignature),
+        }
+    }
+
     let spec: ImageSpec = spec
         .as_str()
         .try_into()
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+        .map_err(|e: anyhow::Error| ThumborError::InvalidSpec(e.to_string()))?;
 
-    let url: &str = &percent_decode_str(&url).decode_utf8_lossy();
-    let data = retrieve_image(&url, cache)
-        .await
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    // answered before the source is loaded, so a revalidation costs no fetch
+    let etag = http_cache::etag(url, &String::from(&spec), settings.engine, accept, &settings.output);
+    if http_cache::not_modified(&headers, &etag) {
+        let response_headers = http_cache::caching(&etag, None, settings.cache.max_age);
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
 
-    let mut engine: Photon = data
-        .try_into()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    engine.apply(&spec.specs);
+    let source = load_source(url, &policy, cache, disk_cache, &fetches, &storage);
+    let Source { data, last_modified } = within(deadline, source).await?;
+    let format = format::negotiate(spec.output.as_ref(), accept, &data, &settings.output);
 
-    let image = engine.generate(ImageOutputFormat::Jpeg(85));
-    info!("Finished processing: image size {}", image.len());
+    let mut response_headers = http_cache::caching(&etag, last_modified.as_deref(), settings.cache.max_age);
 
-    let mut headers = HeaderMap::new();
-    headers.insert("content-type", HeaderValue::from_static("image/jpeg"));
-    
-    Ok((headers, image))
+    let image = within(deadline, render(url, &spec, data, format, &settings, &pool, &result_cache)).await?;
+    METRICS.output(format.content_type(), image.len());
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(format.content_type()));
+    Ok((response_headers, image).into_response())
 }
 
-#[instrument(level = "info", skip(cache))]
-async fn retrieve_image(url: &str, cache: Cache) -> Result<Bytes> {
-    let mut hasher = DefaultHasher::new();
-    url.hash(&mut hasher);
-    let key = hasher.finish();
+// Signed like an image url, with `imageset:<w>:<f>:<spec>` as the spec, `f` empty when not given.
+// Answers with the manifest, or with every image when the client accepts multipart/mixed
+#[allow(clippy::too_many_arguments)]
+async fn image_set(
+    Path(Params { spec, url }): Path<Params>,
+    Query(SetQuery { w, f, s: signature }): Query<SetQuery>,
+    Extension(signer): Extension<Signer>,
+    Extension(policy): Extension<Policy>,
+    Extension(cache): Extension<Cache>,
+    Extension(disk_cache): Extension<SourceDiskCache>,
+    Extension(result_cache): Extension<ResultCache>,
+    Extension(pool): Extension<Workers>,
+    Extension(fetches): Extension<Fetches>,
+    Extension(storage): Extension<Store>,
+    Extension(settings): Extension<Settings>,
+    headers: HeaderMap,
+) -> Result<Response, ThumborError> {
+    let deadline = Deadline::after(settings.limits.deadline());
+    let url: &str = &percent_decode_str(&url).decode_utf8_lossy();
+
+    if let Some(signer) = &signer {
+        let signed = format!("imageset:{}:{}:{}", w, f.as_deref().unwrap_or_default(), spec);
+        match signature {
+            Some(s) if signer.verify(&signed, url, &s) => {}
+            _ => return Err(ThumborError::InvalidSignature),
+        }
+    }
+
+    let invalid = |e: anyhow::Error| ThumborError::InvalidSpec(e.to_string());
+    let base: ImageSpec = spec.as_str().try_into().map_err(invalid)?;
+    let widths = imageset::parse_widths(&w).map_err(invalid)?;
+    let formats = imageset::parse_formats(f.as_deref()).map_err(invalid)?;
+    let set = ImageSet::new(&base, url, &widths, &formats, signer.as_deref());
+
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if !format::accepts(accept, "multipart/mixed") {
+        return Ok(Json(set).into_response());
+    }
+
+    let images: Vec<_> = set.sources.into_iter().flat_map(|source| source.images).collect();
+    if images.len() > settings.limits.max_set_images {
+        return Err(ThumborError::InvalidSpec(format!(
+            "at most {} images are rendered in one response, {} were requested",
+            settings.limits.max_set_images,
+            images.len()
+        )));
+    }
 
-    let g = &mut cache.lock().await;
-    let data = match g.get(&key) {
-        Some(v) => {
-            info!("Match cache {}", key);
-            v.to_owned()
+    let source = load_source(url, &policy, cache, disk_cache, &fetches, &storage);
+    let Source { data, .. } = within(deadline, source).await?;
+
+    // every image goes to the pool at once; dropping the set on the deadline cancels what is left
+    let mut jobs = JoinSet::new();
+    for (index, image) in images.iter().enumerate() {
+        // the parts are for whoever asked for them, so auto goes by the source alone
+        let format = format::negotiate(image.spec.output.as_ref(), "", &data, &settings.output);
+        let (url, spec, data) = (url.to_owned(), image.spec.clone(), data.clone());
+        let (settings, pool, result_cache) = (settings.clone(), pool.clone(), result_cache.clone());
+        jobs.spawn(async move {
+            let rendered = render(&url, &spec, data, format, &settings, &pool, &result_cache).await;
+            (index, format, rendered)
+        });
+    }
+    let rendered = within(deadline, async {
+        let mut rendered = vec![None; images.len()];
+        while let Some(job) = jobs.join_next().await {
+            let (index, format, image) = job.map_err(|e| PoolError::Failed(e.to_string()))?;
+            rendered[index] = Some((format, image?));
         }
-        None => {
-            info!("Retrieve url");
-            let resp = reqwest::get(url).await?;
-            let data = resp.bytes().await?;
-            g.put(key, data.clone());
-            data
+        Ok::<_, ThumborError>(rendered)
+    })
+    .await?;
+
+    let mut multipart = Multipart::default();
+    for (image, (format, rendered)) in images.iter().zip(rendered.into_iter().flatten()) {
+        METRICS.output(format.content_type(), rendered.len());
+        multipart.part(format.content_type(), &image.url, &rendered);
+    }
+    Ok(([(header::CONTENT_TYPE, multipart.content_type())], multipart.finish()).into_response())
+}
+
+// Signed like an image url, with "info" as the spec
+#[allow(clippy::too_many_arguments)]
+async fn image_info(
+    Path(url): Path<String>,
+    Query(SignatureQuery { s: signature }): Query<SignatureQuery>,
+    Extension(signer): Extension<Signer>,
+    Extension(policy): Extension<Policy>,
+    Extension(cache): Extension<Cache>,
+    Extension(disk_cache): Extension<SourceDiskCache>,
+    Extension(pool): Extension<Workers>,
+    Extension(fetches): Extension<Fetches>,
+    Extension(storage): Extension<Store>,
+    Extension(settings): Extension<Settings>,
+) -> Result<Json<ImageInfo>, ThumborError> {
+    let deadline = Deadline::after(settings.limits.deadline());
+    let url: &str = &percent_decode_str(&url).decode_utf8_lossy();
+
+    if let Some(signer) = signer {
+        match signature {
+            Some(s) if signer.verify("info", url, &s) => {}
+            _ => return Err(ThumborError::InvalidSignature),
         }
+    }
+
+    let source = load_source(url, &policy, cache, disk_cache, &fetches, &storage);
+    let Source { data, .. } = within(deadline, source).await?;
+    let info = within(deadline, pool.run(move || info::inspect(&data))).await??;
+    Ok(Json(info))
+}
+
+// Signed like an image url, with "watermarks" as the spec and `<name>/<sha256 of the body>` as the url
+async fn upload_watermark(
+    Path(name): Path<String>,
+    Query(SignatureQuery { s: signature }): Query<SignatureQuery>,
+    Extension(signer): Extension<Signer>,
+    body: Bytes,
+) -> Result<StatusCode, ThumborError> {
+    let signer = signer.ok_or(ThumborError::SecretRequired)?;
+    match signature {
+        Some(s) if signer.verify("watermarks", &format!("{}/{}", name, content_id(&body)), &s) => {}
+        _ => return Err(ThumborError::InvalidSignature),
+    }
+
+    assets::register_watermark(&name, &body)?;
+    info!("Registered watermark {}", name);
+    Ok(StatusCode::CREATED)
+}
+
+// Signed like an image url, with "upload" as the spec and the sha256 of the body, its id, as the url
+async fn upload(
+    Query(SignatureQuery { s: signature }): Query<SignatureQuery>,
+    Extension(signer): Extension<Signer>,
+    Extension(storage): Extension<Store>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<Uploaded>), ThumborError> {
+    // anyone could fill the disk otherwise
+    let signer = signer.ok_or(ThumborError::SecretRequired)?;
+    match signature {
+        Some(s) if signer.verify("upload", &content_id(&body), &s) => {}
+        _ => return Err(ThumborError::InvalidSignature),
+    }
+
+    // only the header is checked here, a broken image fails when it is processed
+    image::guess_format(&body).map_err(|e| ThumborError::Decode(e.to_string()))?;
+    let id = storage.put(body).await?;
+    info!("Stored upload {}", id);
+    let url = format!("{}{}", LOCAL_PREFIX, id);
+    Ok((StatusCode::CREATED, Json(Uploaded { id, url })))
+}
+
+// The process is up and serving
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+// Ready for more traffic unless every worker is busy and the queue is full
+async fn readyz(Extension(pool): Extension<Workers>) -> (StatusCode, &'static str) {
+    match pool.is_full() {
+        true => (StatusCode::SERVICE_UNAVAILABLE, "overloaded"),
+        false => (StatusCode::OK, "ready"),
+    }
+}
+
+// Prometheus text format
+async fn metrics(
+    Extension(pool): Extension<Workers>,
+    Extension(result_cache): Extension<ResultCache>,
+    Extension(disk_cache): Extension<SourceDiskCache>,
+) -> impl IntoResponse {
+    let (result_cache_entries, result_cache_bytes) = {
+        let cache = result_cache.lock().await;
+        (cache.len(), cache.size())
     };
+    let disk_cache_bytes = disk_cache.size();
+    let body = METRICS.render(&[
+        ("thumbor_pool_running", "Jobs holding a worker.", pool.running() as f64),
+        ("thumbor_pool_waiting", "Jobs waiting for a worker.", pool.waiting() as f64),
+        ("thumbor_result_cache_entries", "Number of cached results.", result_cache_entries as f64),
+        ("thumbor_result_cache_bytes", "Size of the cached results.", result_cache_bytes as f64),
+        ("thumbor_disk_cache_bytes", "Size of the sources cached on disk.", disk_cache_bytes as f64),
+    ]);
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+// Counts every response by the route it matched, `unmatched` for none, and its status
+async fn count_requests<B>(request: Request<B>, next: Next<B>) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |path| path.as_str())
+        .to_owned();
+    let response = next.run(request).await;
+    METRICS.request(&route, response.status().as_u16());
+    response
+}
+
+// The processed image from the result cache, or processed on the pool and cached
+async fn render(
+    url: &str,
+    spec: &ImageSpec,
+    data: Bytes,
+    format: OutputFormat,
+    settings: &Config,
+    pool: &Workers,
+    result_cache: &ResultCache,
+) -> Result<Bytes, ThumborError> {
+    let key = result_key(url, spec, format);
+    let (cached, hit_ratio) = {
+        let mut g = result_cache.lock().await;
+        (g.get(&key), g.hit_ratio())
+    };
+    METRICS.cache("result", cached.is_some());
+    if let Some(image) = cached {
+        info!("Match result cache {}, hit ratio {:.2}", key, hit_ratio);
+        return Ok(image);
+    }
+
+    let exif = spec.keep_metadata().then(|| metadata::exif(&data)).flatten();
+    let (engine, specs) = (settings.engine, spec.specs.clone());
+    let image = pool.run(move || engine.run(data, &specs, format)).await??;
+    let image = match exif {
+        Some(exif) => metadata::embed_exif(image, format, &exif),
+        None => image,
+    };
+    let image = Bytes::from(image);
+    info!(
+        "Finished processing: image size {}, format {:?}, result cache hit ratio {:.2}",
+        image.len(),
+        format,
+        hit_ratio
+    );
+    result_cache.lock().await.put(key, image.clone());
+    Ok(image)
+}
+
+// The processed image depends on the source, every spec and the negotiated output format
+fn result_key(url: &str, spec: &ImageSpec, format: OutputFormat) -> u64 {
+    let spec: String = spec.into();
+    cache_key((url, spec, format))
+}
+
+// When a request runs out of time, and how long it was given
+#[derive(Clone, Copy)]
+struct Deadline {
+    at: Instant,
+    limit: Duration,
+}
+
+impl Deadline {
+    fn after(limit: Duration) -> Self {
+        Self {
+            at: Instant::now() + limit,
+            limit,
+        }
+    }
+}
+
+// Fail with a 504 once the request's deadline has passed; work already on the pool still finishes
+async fn within<T, E>(deadline: Deadline, f: impl Future<Output = Result<T, E>>) -> Result<T, ThumborError>
+where
+    ThumborError: From<E>,
+{
+    match tokio::time::timeout_at(deadline.at, f).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(ThumborError::Deadline(deadline.limit)),
+    }
+}
 
-    Ok(data)
+// Uploaded originals are read from storage, anything else is downloaded
+async fn load_source(
+    url: &str,
+    policy: &SourcePolicy,
+    cache: Cache,
+    disk_cache: SourceDiskCache,
+    fetches: &Fetches,
+    storage: &Store,
+) -> Result<Source, ThumborError> {
+    match url.strip_prefix(LOCAL_PREFIX) {
+        Some(id) => Ok(storage.get(id).await?.into()),
+        None => Ok(retrieve_image(url, policy, cache, disk_cache, fetches).await?),
+    }
+}
+
+#[instrument(level = "info", skip(policy, cache, disk_cache, fetches))]
+async fn retrieve_image(
+    url: &str,
+    policy: &SourcePolicy,
+    cache: Cache,
+    disk_cache: SourceDiskCache,
+    fetches: &Fetches,
+) -> Result<Source, FetchError> {
+    let key = cache_key(url);
+
+    let cached = cache.lock().await.get(&key).cloned();
+    METRICS.cache("source", cached.is_some());
+    if let Some(source) = cached {
+        info!("Match cache {}", key);
+        return Ok(source);
+    }
+
+    // concurrent requests for one url share a single disk read or download,
+    // and no lock is held while it runs
+    fetches
+        .run(key, || async {
+            let cached = disk_cache.get(url).await;
+            METRICS.cache("disk", cached.is_some());
+            let source = match cached {
+                Some((data, last_modified)) => {
+                    info!("Match disk cache {}", key);
+                    Source { data, last_modified }
+                }
+                None => {
+                    info!("Retrieve url");
+                    let started = Instant::now();
+                    let source = policy.fetch(url).await;
+                    METRICS.fetch(started.elapsed(), source.is_ok());
+                    let source = source.map_err(|e| {
+                        warn!("Failed to retrieve {}: {}", url, e);
+                        e
+                    })?;
+                    let last_modified = source.last_modified.as_deref();
+                    if let Err(e) = disk_cache.put(url, &source.data, last_modified).await {
+                        warn!("Failed to write disk cache: {}", e);
+                    }
+                    source
+                }
+            };
+            cache.lock().await.put(key, source.clone());
+            Ok(source)
+        })
+        .await
 }
 
 #[test]
 fn print_test_url() {
+    use pb::{filter, resize, Spec};
+    use percent_encoding::{percent_encode, AsciiSet, CONTROLS};
     use std::borrow::Borrow;
     let url = "https://images.pexels.com/photos/14361428/pexels-photo-14361428.jpeg?auto=compress&cs=tinysrgb&w=1260&h=750&dpr=2";
     let spec1 = Spec::new_resize(500, 800, resize::SampleFilter::CatmullRom);
@@ -110,4 +592,5 @@ fn print_test_url() {
     const GENERAL_URL: &AsciiSet = &CONTROLS.add(b' ').add(b'/').add(b'?').add(b'#').add(b'=').add(b'&').add(b'%');
     let test_image = percent_encode(url.as_bytes(), GENERAL_URL).to_string();
     println!("test url: http://localhost:3000/image/{}/{}", s, test_image);
+    println!("readable: http://localhost:3000/image/{}/{}", image_spec, test_image);
 }
@@ -0,0 +1,235 @@
+use std::io::Cursor;
+
+use exif::{In, Tag};
+use image::io::Reader as ImageReader;
+use image::{imageops, ImageFormat, RgbaImage};
+
+use crate::format::OutputFormat;
+
+const ORIENTATION_TAG: u16 = 0x0112;
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const WEBP_EXIF_FLAG: u8 = 0x08;
+const WEBP_ALPHA_FLAG: u8 = 0x10;
+
+// EXIF orientation of JPEG, TIFF, PNG, WebP and HEIF images
+pub fn orientation(data: &[u8]) -> Option<u32> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok()?;
+    let orientation = exif.get_field(Tag::Orientation, In::PRIMARY)?.value.get_uint(0)?;
+    (1..=8).contains(&orientation).then_some(orientation)
+}
+
+// Rotate and flip the pixels so the image looks right without its orientation tag
+pub fn orient(img: RgbaImage, orientation: u32) -> RgbaImage {
+    match orientation {
+        2 => imageops::flip_horizontal(&img),
+        3 => imageops::rotate180(&img),
+        4 => imageops::flip_vertical(&img),
+        // transpose
+        5 => imageops::flip_horizontal(&imageops::rotate90(&img)),
+        6 => imageops::rotate90(&img),
+        // transverse
+        7 => imageops::flip_horizontal(&imageops::rotate270(&img)),
+        8 => imageops::rotate270(&img),
+        _ => img,
+    }
+}
+
+// The source's EXIF as a TIFF block, with the orientation reset since the pixels are oriented on decode
+pub fn exif(data: &[u8]) -> Option<Vec<u8>> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok()?;
+    let mut tiff = exif.buf().to_vec();
+    reset_orientation(&mut tiff);
+    Some(tiff)
+}
+
+fn reset_orientation(tiff: &mut [u8]) -> Option<()> {
+    let big_endian = match tiff.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |buf: &[u8], i: usize| -> Option<u16> {
+        let b = buf.get(i..i + 2)?.try_into().ok()?;
+        Some(if big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) })
+    };
+    let u32_at = |buf: &[u8], i: usize| -> Option<u32> {
+        let b = buf.get(i..i + 4)?.try_into().ok()?;
+        Some(if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    };
+
+    let ifd = u32_at(tiff, 4)? as usize;
+    for i in 0..u16_at(tiff, ifd)? as usize {
+        let entry = ifd + 2 + i * 12;
+        if u16_at(tiff, entry)? == ORIENTATION_TAG {
+            let one = if big_endian { 1u16.to_be_bytes() } else { 1u16.to_le_bytes() };
+            tiff.get_mut(entry + 8..entry + 10)?.copy_from_slice(&one);
+        }
+    }
+    Some(())
+}
+
+// Write a TIFF block into an encoded image; AVIF and GIF come back unchanged
+pub fn embed_exif(image: Vec<u8>, format: OutputFormat, tiff: &[u8]) -> Vec<u8> {
+    match format {
+        OutputFormat::Jpeg(_) => embed_jpeg(image, tiff),
+        OutputFormat::Png => embed_png(image, tiff),
+        OutputFormat::WebP(_) => embed_webp(image, tiff),
+        OutputFormat::Avif(_) | OutputFormat::Gif => image,
+    }
+}
+
+// An APP1 segment right after SOI and JFIF's APP0
+fn embed_jpeg(image: Vec<u8>, tiff: &[u8]) -> Vec<u8> {
+    let len = 2 + EXIF_HEADER.len() + tiff.len();
+    if len > u16::MAX as usize || !image.starts_with(&[0xff, 0xd8]) {
+        return image;
+    }
+    let at = match image.get(2..6) {
+        Some([0xff, 0xe0, hi, lo]) => 4 + u16::from_be_bytes([*hi, *lo]) as usize,
+        _ => 2,
+    };
+    if at > image.len() {
+        return image;
+    }
+
+    let mut out = Vec::with_capacity(image.len() + len + 2);
+    out.extend_from_slice(&image[..at]);
+    out.extend_from_slice(&[0xff, 0xe1]);
+    out.extend_from_slice(&(len as u16).to_be_bytes());
+    out.extend_from_slice(EXIF_HEADER);
+    out.extend_from_slice(tiff);
+    out.extend_from_slice(&image[at..]);
+    out
+}
+
+// An eXIf chunk right after IHDR
+fn embed_png(image: Vec<u8>, tiff: &[u8]) -> Vec<u8> {
+    // signature, then IHDR: length, type, 13 bytes of data and crc
+    const IHDR_END: usize = 8 + 4 + 4 + 13 + 4;
+    if image.get(12..16) != Some(b"IHDR") || image.len() < IHDR_END {
+        return image;
+    }
+
+    let mut chunk = Vec::with_capacity(tiff.len() + 12);
+    chunk.extend_from_slice(&(tiff.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(b"eXIf");
+    chunk.extend_from_slice(tiff);
+    let crc = crc32fast::hash(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+
+    let mut out = image;
+    out.splice(IHDR_END..IHDR_END, chunk);
+    out
+}
+
+// An EXIF chunk at the end, turning a simple (VP8/VP8L) file into the extended (VP8X) format
+fn embed_webp(image: Vec<u8>, tiff: &[u8]) -> Vec<u8> {
+    if image.len() < 30 || &image[..4] != b"RIFF" || &image[8..12] != b"WEBP" {
+        return image;
+    }
+
+    let mut out = image[..12].to_vec();
+    match &image[12..16] {
+        b"VP8X" => {
+            out.extend_from_slice(&image[12..]);
+            out[20] |= WEBP_EXIF_FLAG;
+        }
+        kind @ (b"VP8 " | b"VP8L") => {
+            let reader = ImageReader::with_format(Cursor::new(&image), ImageFormat::WebP);
+            let Ok((width, height)) = reader.into_dimensions() else {
+                return image;
+            };
+            // lossless headers carry an alpha_is_used bit after the 14 bit width and height
+            let header = u32::from_le_bytes(image[21..25].try_into().unwrap());
+            let alpha = kind == b"VP8L" && header >> 28 & 1 == 1;
+
+            out.extend_from_slice(b"VP8X");
+            out.extend_from_slice(&10u32.to_le_bytes());
+            out.push(WEBP_EXIF_FLAG | if alpha { WEBP_ALPHA_FLAG } else { 0 });
+            out.extend_from_slice(&[0, 0, 0]);
+            out.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+            out.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+            out.extend_from_slice(&image[12..]);
+        }
+        _ => return image,
+    }
+
+    out.extend_from_slice(b"EXIF");
+    out.extend_from_slice(&(tiff.len() as u32).to_le_bytes());
+    out.extend_from_slice(tiff);
+    if tiff.len() % 2 == 1 {
+        out.push(0);
+    }
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    out
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageOutputFormat, Rgba};
+
+    // a big endian TIFF block with a single orientation entry
+    pub(crate) fn tiff(orientation: u16) -> Vec<u8> {
+        let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01".to_vec();
+        tiff.extend_from_slice(&orientation.to_be_bytes());
+        tiff.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        tiff
+    }
+
+    pub(crate) fn encode(img: &RgbaImage, format: ImageOutputFormat) -> Vec<u8> {
+        let mut buf = Cursor::new(vec![]);
+        let img = match format {
+            ImageOutputFormat::Jpeg(_) => DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(img.clone()).to_rgb8()),
+            _ => DynamicImage::ImageRgba8(img.clone()),
+        };
+        img.write_to(&mut buf, format).unwrap();
+        buf.into_inner()
+    }
+
+    // 2x1: red on the left, blue on the right
+    fn pair() -> RgbaImage {
+        RgbaImage::from_fn(2, 1, |x, _| if x == 0 { Rgba([255, 0, 0, 255]) } else { Rgba([0, 0, 255, 255]) })
+    }
+
+    #[test]
+    fn orient_should_work() {
+        let red = Rgba([255, 0, 0, 255]);
+        assert_eq!(*orient(pair(), 2).get_pixel(1, 0), red);
+        assert_eq!(*orient(pair(), 3).get_pixel(1, 0), red);
+        assert_eq!(*orient(pair(), 4).get_pixel(0, 0), red);
+        for (orientation, y) in [(5, 0), (6, 0), (7, 1), (8, 1)] {
+            let img = orient(pair(), orientation);
+            assert_eq!(img.dimensions(), (1, 2));
+            assert_eq!(*img.get_pixel(0, y), red, "orientation {}", orientation);
+        }
+    }
+
+    #[test]
+    fn embedded_exif_should_be_readable() {
+        let mut tiff = tiff(6);
+        reset_orientation(&mut tiff);
+        for (output, format) in [
+            (ImageOutputFormat::Jpeg(90), OutputFormat::Jpeg(90)),
+            (ImageOutputFormat::Png, OutputFormat::Png),
+            (ImageOutputFormat::WebP, OutputFormat::WebP(80)),
+        ] {
+            let image = encode(&pair(), output);
+            let image = embed_exif(image, format, &tiff);
+            assert_eq!(orientation(&image), Some(1), "{:?}", format);
+            assert_eq!(image::load_from_memory(&image).unwrap().width(), 2, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn exif_should_reset_orientation() {
+        let data = embed_exif(encode(&pair(), ImageOutputFormat::Png), OutputFormat::Png, &tiff(8));
+        assert_eq!(orientation(&data), Some(8));
+        assert_eq!(exif(&data), Some(tiff(1)));
+    }
+}
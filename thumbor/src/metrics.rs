@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+// seconds
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+// bytes, 1 KiB to 16 MiB
+const SIZE_BUCKETS: &[f64] = &[1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0];
+
+lazy_static! {
+    // one registry for the process, so the engines can record without it being passed down
+    pub static ref METRICS: Metrics = Metrics::default();
+}
+
+/// Counters and histograms served on /metrics in the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    // (route, status)
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+    // (cache, hit)
+    caches: Mutex<BTreeMap<(&'static str, bool), u64>>,
+    // by outcome, ok or error
+    fetches: Mutex<BTreeMap<&'static str, Histogram>>,
+    // by spec name
+    transforms: Mutex<BTreeMap<&'static str, Histogram>>,
+    // by content type
+    outputs: Mutex<BTreeMap<&'static str, Histogram>>,
+}
+
+struct Histogram {
+    buckets: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(buckets: &'static [f64]) -> Self {
+        Self {
+            buckets,
+            counts: vec![0; buckets.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        // counts are kept per bucket and added up when rendered
+        if let Some(i) = self.buckets.iter().position(|&le| value <= le) {
+            self.counts[i] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    pub fn request(&self, route: &str, status: u16) {
+        *self.requests.lock().unwrap().entry((route.to_string(), status)).or_default() += 1;
+    }
+
+    // cache is one of source, disk or result
+    pub fn cache(&self, cache: &'static str, hit: bool) {
+        *self.caches.lock().unwrap().entry((cache, hit)).or_default() += 1;
+    }
+
+    pub fn fetch(&self, elapsed: Duration, ok: bool) {
+        let outcome = if ok { "ok" } else { "error" };
+        observe(&self.fetches, outcome, LATENCY_BUCKETS, elapsed.as_secs_f64());
+    }
+
+    pub fn transform(&self, spec: &'static str, elapsed: Duration) {
+        observe(&self.transforms, spec, LATENCY_BUCKETS, elapsed.as_secs_f64());
+    }
+
+    pub fn output(&self, content_type: &'static str, bytes: usize) {
+        observe(&self.outputs, content_type, SIZE_BUCKETS, bytes as f64);
+    }
+
+    // Everything recorded so far, followed by the given gauges
+    pub fn render(&self, gauges: &[(&str, &str, f64)]) -> String {
+        let mut out = String::new();
+
+        header(&mut out, "thumbor_requests_total", "Requests by route and status.", "counter");
+        for ((route, status), count) in self.requests.lock().unwrap().iter() {
+            let labels = format!("route=\"{}\",status=\"{}\"", escape(route), status);
+            sample(&mut out, "thumbor_requests_total", &labels, *count as f64);
+        }
+
+        let caches = self.caches.lock().unwrap();
+        header(&mut out, "thumbor_cache_requests_total", "Cache lookups by cache and outcome.", "counter");
+        for ((cache, hit), count) in caches.iter() {
+            let labels = format!("cache=\"{}\",outcome=\"{}\"", cache, if *hit { "hit" } else { "miss" });
+            sample(&mut out, "thumbor_cache_requests_total", &labels, *count as f64);
+        }
+        header(&mut out, "thumbor_cache_hit_ratio", "Share of cache lookups that hit.", "gauge");
+        for cache in ["source", "disk", "result"] {
+            let hits = caches.get(&(cache, true)).copied().unwrap_or(0);
+            let misses = caches.get(&(cache, false)).copied().unwrap_or(0);
+            if hits + misses > 0 {
+                let ratio = hits as f64 / (hits + misses) as f64;
+                sample(&mut out, "thumbor_cache_hit_ratio", &format!("cache=\"{}\"", cache), ratio);
+            }
+        }
+        drop(caches);
+
+        let histograms = [
+            ("thumbor_fetch_duration_seconds", "Source download time.", "outcome", &self.fetches),
+            ("thumbor_transform_duration_seconds", "Time spent in each spec.", "spec", &self.transforms),
+            ("thumbor_output_bytes", "Size of the images served.", "content_type", &self.outputs),
+        ];
+        for (name, help, label, family) in histograms {
+            header(&mut out, name, help, "histogram");
+            for (value, histogram) in family.lock().unwrap().iter() {
+                render_histogram(&mut out, name, &format!("{}=\"{}\"", label, value), histogram);
+            }
+        }
+
+        for (name, help, value) in gauges {
+            header(&mut out, name, help, "gauge");
+            sample(&mut out, name, "", *value);
+        }
+        out
+    }
+}
+
+fn observe(family: &Mutex<BTreeMap<&'static str, Histogram>>, label: &'static str, buckets: &'static [f64], value: f64) {
+    family
+        .lock()
+        .unwrap()
+        .entry(label)
+        .or_insert_with(|| Histogram::new(buckets))
+        .observe(value);
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: f64) {
+    match labels {
+        "" => writeln!(out, "{} {}", name, value).unwrap(),
+        _ => writeln!(out, "{}{{{}}} {}", name, labels, value).unwrap(),
+    }
+}
+
+fn render_histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    let bucket = format!("{}_bucket", name);
+    let mut cumulative = 0;
+    for (le, count) in histogram.buckets.iter().zip(&histogram.counts) {
+        cumulative += count;
+        sample(out, &bucket, &format!("{},le=\"{}\"", labels, le), cumulative as f64);
+    }
+    sample(out, &bucket, &format!("{},le=\"+Inf\"", labels), histogram.count as f64);
+    sample(out, &format!("{}_sum", name), labels, histogram.sum);
+    sample(out, &format!("{}_count", name), labels, histogram.count as f64);
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_should_be_cumulative() {
+        let metrics = Metrics::default();
+        metrics.transform("resize", Duration::from_millis(3));
+        metrics.transform("resize", Duration::from_millis(40));
+        metrics.transform("resize", Duration::from_secs(60));
+        let out = metrics.render(&[]);
+
+        let line = |s: &str| assert!(out.lines().any(|l| l == s), "missing {:?} in\n{}", s, out);
+        line("# TYPE thumbor_transform_duration_seconds histogram");
+        line("thumbor_transform_duration_seconds_bucket{spec=\"resize\",le=\"0.005\"} 1");
+        line("thumbor_transform_duration_seconds_bucket{spec=\"resize\",le=\"0.025\"} 1");
+        line("thumbor_transform_duration_seconds_bucket{spec=\"resize\",le=\"0.05\"} 2");
+        line("thumbor_transform_duration_seconds_bucket{spec=\"resize\",le=\"10\"} 2");
+        line("thumbor_transform_duration_seconds_bucket{spec=\"resize\",le=\"+Inf\"} 3");
+        line("thumbor_transform_duration_seconds_count{spec=\"resize\"} 3");
+    }
+
+    #[test]
+    fn counters_and_ratios_should_be_rendered() {
+        let metrics = Metrics::default();
+        metrics.request("/image/:spec/:url", 200);
+        metrics.request("/image/:spec/:url", 200);
+        metrics.request("/image/:spec/:url", 403);
+        for hit in [true, true, true, false] {
+            metrics.cache("result", hit);
+        }
+        metrics.output("image/webp", 2000);
+        let out = metrics.render(&[("thumbor_pool_waiting", "Jobs waiting for a worker.", 2.0)]);
+
+        let line = |s: &str| assert!(out.lines().any(|l| l == s), "missing {:?} in\n{}", s, out);
+        line("thumbor_requests_total{route=\"/image/:spec/:url\",status=\"200\"} 2");
+        line("thumbor_requests_total{route=\"/image/:spec/:url\",status=\"403\"} 1");
+        line("thumbor_cache_requests_total{cache=\"result\",outcome=\"miss\"} 1");
+        line("thumbor_cache_hit_ratio{cache=\"result\"} 0.75");
+        line("thumbor_output_bytes_bucket{content_type=\"image/webp\",le=\"1024\"} 0");
+        line("thumbor_output_bytes_bucket{content_type=\"image/webp\",le=\"4096\"} 1");
+        line("thumbor_output_bytes_sum{content_type=\"image/webp\"} 2000");
+        line("# TYPE thumbor_pool_waiting gauge");
+        line("thumbor_pool_waiting 2");
+        assert!(!out.contains("cache=\"disk\""));
+    }
+
+    #[test]
+    fn label_values_should_be_escaped() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
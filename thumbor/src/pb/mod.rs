@@ -1,20 +1,50 @@
 use base64::engine::{self, general_purpose};
 use base64::Engine as _;
 use base64::alphabet;
+use image::imageops::FilterType;
 use photon_rs::transform::SamplingFilter;
 use prost::Message;
 use std::convert::TryFrom;
 
 mod abi;
+mod syntax;
 pub use abi::ImageSpec;
 pub use abi::Spec;
 pub use abi::spec;
 pub use abi::{Resize, Crop, Fliph, Flipv, Contrast, Filter, Watermark};
-pub use abi::{resize, filter};
+pub use abi::{Rotate, Blur, Sharpen, Grayscale, Sepia, Brightness, Saturation, Hue, Padding, RoundCorners, Text};
+pub use abi::Frame;
+pub use abi::{Anchor, Color};
+pub use abi::Output;
+pub use abi::{resize, filter, output};
 
 impl ImageSpec {
     pub fn new(specs: Vec<Spec>) -> Self {
-        Self { specs }
+        Self {
+            specs,
+            output: None,
+        }
+    }
+
+    pub fn with_output(mut self, format: output::Format, quality: u32) -> Self {
+        let metadata = self.output.map(|o| o.metadata).unwrap_or_default();
+        self.output = Some(Output {
+            format: format as i32,
+            quality,
+            metadata,
+        });
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: output::Metadata) -> Self {
+        self.output.get_or_insert_with(Output::default).metadata = metadata as i32;
+        self
+    }
+
+    pub fn keep_metadata(&self) -> bool {
+        self.output
+            .as_ref()
+            .is_some_and(|o| o.metadata == output::Metadata::Keep as i32)
     }
 }
 
@@ -26,18 +56,22 @@ impl From<&ImageSpec> for String {
     }
 }
 
+// Either the readable syntax or base64 encoded protobuf, which never contains `:` or `,`
 impl TryFrom<&str> for ImageSpec {
     type Error = anyhow::Error;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
+        if value.contains([':', ',']) || syntax::BARE.contains(&value) {
+            return value.parse();
+        }
         const CUSTOM_ENGINE: engine::GeneralPurpose = engine::GeneralPurpose::new(&alphabet::URL_SAFE, general_purpose::NO_PAD);
-        let data = CUSTOM_ENGINE.decode(&value)?;
+        let data = CUSTOM_ENGINE.decode(value)?;
         Ok(ImageSpec::decode(&data[..])?)
     }
 }
 
 impl filter::Filter {
-    pub fn to_str(&self) -> Option<&'static str> {
+    pub fn to_str(self) -> Option<&'static str> {
         match self {
             filter::Filter::Unspecified => None,
             filter::Filter::Oceanic => Some("oceanic"),
@@ -47,6 +81,32 @@ impl filter::Filter {
     }
 }
 
+impl spec::Data {
+    pub fn name(&self) -> &'static str {
+        match self {
+            spec::Data::Resize(_) => "resize",
+            spec::Data::Crop(_) => "crop",
+            spec::Data::Flipv(_) => "flipv",
+            spec::Data::Fliph(_) => "fliph",
+            spec::Data::Contrast(_) => "contrast",
+            spec::Data::Filter(_) => "filter",
+            spec::Data::Watermark(_) => "watermark",
+            spec::Data::Rotate(_) => "rotate",
+            spec::Data::Blur(_) => "blur",
+            spec::Data::Sharpen(_) => "sharpen",
+            spec::Data::Grayscale(_) => "grayscale",
+            spec::Data::Sepia(_) => "sepia",
+            spec::Data::Brightness(_) => "brightness",
+            spec::Data::Saturation(_) => "saturation",
+            spec::Data::Hue(_) => "hue",
+            spec::Data::Padding(_) => "padding",
+            spec::Data::RoundCorners(_) => "round_corners",
+            spec::Data::Text(_) => "text",
+            spec::Data::Frame(_) => "frame",
+        }
+    }
+}
+
 impl From<resize::SampleFilter> for SamplingFilter {
     fn from(v: resize::SampleFilter) -> Self {
         match v {
@@ -60,6 +120,19 @@ impl From<resize::SampleFilter> for SamplingFilter {
     }
 }
 
+impl From<resize::SampleFilter> for FilterType {
+    fn from(v: resize::SampleFilter) -> Self {
+        match v {
+            resize::SampleFilter::Undefined => FilterType::Nearest,
+            resize::SampleFilter::Nearest => FilterType::Nearest,
+            resize::SampleFilter::Triangle => FilterType::Triangle,
+            resize::SampleFilter::CatmullRom => FilterType::CatmullRom,
+            resize::SampleFilter::Gaussian => FilterType::Gaussian,
+            resize::SampleFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
 impl Spec {
     pub fn new_resize_seam_carve(width: u32, height: u32) -> Self {
         Self {
@@ -68,6 +141,7 @@ impl Spec {
                 height,
                 rtype: resize::ResizeType::SeamCarve as i32,
                 filter: resize::SampleFilter::Undefined as i32,
+                ..Default::default()
             })),
         }
     }
@@ -79,6 +153,27 @@ impl Spec {
                 height,
                 rtype: resize::ResizeType::Normal as i32,
                 filter: filter as i32,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn new_resize_fit(
+        width: u32,
+        height: u32,
+        fit: resize::Fit,
+        gravity: resize::Gravity,
+        background: Option<Color>,
+    ) -> Self {
+        Self {
+            data: Some(spec::Data::Resize(Resize {
+                width,
+                height,
+                rtype: resize::ResizeType::Normal as i32,
+                filter: resize::SampleFilter::CatmullRom as i32,
+                fit: fit as i32,
+                gravity: gravity as i32,
+                background,
             })),
         }
     }
@@ -93,14 +188,147 @@ impl Spec {
 
     pub fn new_watermark(x: u32, y: u32) -> Self {
         Self {
-            data: Some(spec::Data::Watermark(Watermark { x, y })),
+            data: Some(spec::Data::Watermark(Watermark {
+                x,
+                y,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn new_named_watermark(name: &str, anchor: Anchor, x: u32, y: u32, scale: f32, opacity: f32) -> Self {
+        Self {
+            data: Some(spec::Data::Watermark(Watermark {
+                x,
+                y,
+                name: name.to_owned(),
+                scale,
+                opacity,
+                anchor: anchor as i32,
+                tile: false,
+            })),
+        }
+    }
+
+    pub fn new_tiled_watermark(name: &str, gap: u32, scale: f32, opacity: f32) -> Self {
+        Self {
+            data: Some(spec::Data::Watermark(Watermark {
+                x: gap,
+                y: gap,
+                name: name.to_owned(),
+                scale,
+                opacity,
+                anchor: Anchor::TopLeft as i32,
+                tile: true,
+            })),
+        }
+    }
+
+    pub fn new_text(text: &str, font: &str, size: f32, color: Option<Color>, anchor: Anchor, x: u32, y: u32) -> Self {
+        Self {
+            data: Some(spec::Data::Text(Text {
+                text: text.to_owned(),
+                font: font.to_owned(),
+                size,
+                color,
+                x,
+                y,
+                anchor: anchor as i32,
+            })),
+        }
+    }
+
+    pub fn new_frame(index: u32) -> Self {
+        Self {
+            data: Some(spec::Data::Frame(Frame { index })),
+        }
+    }
+
+    pub fn new_rotate(angle: f32, background: Option<Color>) -> Self {
+        Self {
+            data: Some(spec::Data::Rotate(Rotate { angle, background })),
+        }
+    }
+
+    pub fn new_blur(radius: u32) -> Self {
+        Self {
+            data: Some(spec::Data::Blur(Blur { radius })),
+        }
+    }
+
+    pub fn new_sharpen(sigma: f32, threshold: i32) -> Self {
+        Self {
+            data: Some(spec::Data::Sharpen(Sharpen { sigma, threshold })),
+        }
+    }
+
+    pub fn new_grayscale() -> Self {
+        Self {
+            data: Some(spec::Data::Grayscale(Grayscale {})),
+        }
+    }
+
+    pub fn new_sepia() -> Self {
+        Self {
+            data: Some(spec::Data::Sepia(Sepia {})),
+        }
+    }
+
+    pub fn new_brightness(brightness: i32) -> Self {
+        Self {
+            data: Some(spec::Data::Brightness(Brightness { brightness })),
+        }
+    }
+
+    pub fn new_saturation(saturation: f32) -> Self {
+        Self {
+            data: Some(spec::Data::Saturation(Saturation { saturation })),
+        }
+    }
+
+    pub fn new_hue(degrees: f32) -> Self {
+        Self {
+            data: Some(spec::Data::Hue(Hue { degrees })),
+        }
+    }
+
+    pub fn new_padding(top: u32, right: u32, bottom: u32, left: u32, color: Option<Color>) -> Self {
+        Self {
+            data: Some(spec::Data::Padding(Padding {
+                top,
+                right,
+                bottom,
+                left,
+                color,
+            })),
+        }
+    }
+
+    pub fn new_round_corners(radius: u32) -> Self {
+        Self {
+            data: Some(spec::Data::RoundCorners(RoundCorners { radius })),
         }
     }
 }
 
+impl Color {
+    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self {
+            r: r as u32,
+            g: g as u32,
+            b: b as u32,
+            a: a as u32,
+        }
+    }
+
+    pub fn to_rgba(&self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a].map(|v| v.min(255) as u8)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Spec, ImageSpec};
+    use super::{Anchor, Color, Spec, ImageSpec};
     use super::{filter, resize};
     use std::borrow::Borrow;
     use std::convert::TryInto;
@@ -113,4 +341,20 @@ mod tests {
         let s: String = image_spec.borrow().into();
         assert_eq!(image_spec, s.as_str().try_into().unwrap());
     }
+
+    #[test]
+    fn new_transforms_could_be_decoded() {
+        let image_spec = ImageSpec::new(vec![
+            Spec::new_rotate(90.0, None),
+            Spec::new_blur(3),
+            Spec::new_padding(10, 10, 10, 10, Some(Color::new(255, 255, 255, 255))),
+            Spec::new_round_corners(16),
+            Spec::new_resize_fit(300, 0, resize::Fit::Cover, resize::Gravity::Attention, None),
+            Spec::new_named_watermark("logo", Anchor::BottomRight, 10, 10, 0.2, 0.5),
+            Spec::new_text("hello", "", 32.0, None, Anchor::Top, 0, 8),
+            Spec::new_frame(2),
+        ]);
+        let s: String = image_spec.borrow().into();
+        assert_eq!(image_spec, s.as_str().try_into().unwrap());
+    }
 }
@@ -0,0 +1,436 @@
+// Human readable specs, e.g. `resize:500x800:fit=cover,filter:marine,wm:20x20`:
+// comma separated transforms, each a name followed by `:` separated positional
+// arguments and `key=value` options, where options at their default are left out.
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+
+use super::{spec, Anchor, Color, ImageSpec, Output, Spec};
+use super::{output, resize, filter};
+use super::{Resize, Crop, Fliph, Flipv, Contrast, Filter, Watermark};
+use super::{Rotate, Blur, Sharpen, Grayscale, Sepia, Brightness, Saturation, Hue, Padding, RoundCorners, Text};
+use super::Frame;
+
+// characters with a meaning in the syntax, escaped inside free text
+const TEXT_ENCODE_SET: &AsciiSet = &CONTROLS.add(b',').add(b':').add(b'=').add(b'%');
+
+// transforms without arguments, which look like base64 when on their own
+pub(super) const BARE: [&str; 4] = ["flipv", "fliph", "grayscale", "sepia"];
+
+impl fmt::Display for ImageSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut ops: Vec<Op> = self.specs.iter().filter_map(|s| s.data.as_ref()).map(Op::from).collect();
+        if let Some(o) = &self.output {
+            ops.push(
+                Op::new("format")
+                    .arg(enum_str(o.format, output::Format::from_i32, output::Format::as_str_name))
+                    .opt("quality", nonzero(o.quality))
+                    .opt("metadata", enum_opt(o.metadata, output::Metadata::from_i32, output::Metadata::as_str_name)),
+            );
+        }
+        let ops: Vec<String> = ops.iter().map(Op::to_string).collect();
+        write!(f, "{}", ops.join(","))
+    }
+}
+
+impl FromStr for ImageSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut image_spec = ImageSpec::new(vec![]);
+        for item in s.split(',').filter(|item| !item.is_empty()) {
+            let mut p = Parser::new(item);
+            if p.name == "format" {
+                if image_spec.output.is_some() {
+                    bail!("format is given twice");
+                }
+                image_spec.output = Some(Output {
+                    format: p.enum_arg(output::Format::from_str_name)?,
+                    quality: p.opt("quality")?.unwrap_or_default(),
+                    metadata: p.enum_opt("metadata", output::Metadata::from_str_name)?,
+                });
+            } else {
+                let data = p.data()?;
+                image_spec.specs.push(Spec { data: Some(data) });
+            }
+            p.done()?;
+        }
+        Ok(image_spec)
+    }
+}
+
+// One transform being written out
+struct Op {
+    name: &'static str,
+    args: Vec<String>,
+    opts: Vec<(&'static str, String)>,
+}
+
+impl Op {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            args: vec![],
+            opts: vec![],
+        }
+    }
+
+    fn arg(mut self, value: impl ToString) -> Self {
+        self.args.push(value.to_string());
+        self
+    }
+
+    fn opt(mut self, key: &'static str, value: Option<String>) -> Self {
+        if let Some(value) = value {
+            self.opts.push((key, value));
+        }
+        self
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for arg in &self.args {
+            write!(f, ":{}", arg)?;
+        }
+        for (key, value) in &self.opts {
+            write!(f, ":{}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&spec::Data> for Op {
+    fn from(data: &spec::Data) -> Self {
+        match data {
+            spec::Data::Resize(v) => Op::new("resize")
+                .arg(size(v.width, v.height))
+                .opt("type", enum_opt(v.rtype, resize::ResizeType::from_i32, resize::ResizeType::as_str_name))
+                .opt("filter", enum_opt(v.filter, resize::SampleFilter::from_i32, resize::SampleFilter::as_str_name))
+                .opt("fit", enum_opt(v.fit, resize::Fit::from_i32, resize::Fit::as_str_name))
+                .opt("gravity", enum_opt(v.gravity, resize::Gravity::from_i32, resize::Gravity::as_str_name))
+                .opt("bg", v.background.as_ref().map(color_str)),
+            spec::Data::Crop(v) => Op::new("crop").arg(size(v.x1, v.y1)).arg(size(v.x2, v.y2)),
+            spec::Data::Flipv(_) => Op::new("flipv"),
+            spec::Data::Fliph(_) => Op::new("fliph"),
+            spec::Data::Contrast(v) => Op::new("contrast").arg(v.contrast),
+            spec::Data::Filter(v) => {
+                Op::new("filter").arg(enum_str(v.filter, filter::Filter::from_i32, filter::Filter::as_str_name))
+            }
+            spec::Data::Watermark(v) => Op::new("wm")
+                .arg(size(v.x, v.y))
+                .opt("name", (!v.name.is_empty()).then(|| v.name.clone()))
+                .opt("scale", nonzero(v.scale))
+                .opt("opacity", nonzero(v.opacity))
+                .opt("anchor", enum_opt(v.anchor, Anchor::from_i32, Anchor::as_str_name))
+                .opt("tile", v.tile.then(|| "true".to_owned())),
+            spec::Data::Rotate(v) => Op::new("rotate")
+                .arg(v.angle)
+                .opt("bg", v.background.as_ref().map(color_str)),
+            spec::Data::Blur(v) => Op::new("blur").arg(v.radius),
+            spec::Data::Sharpen(v) => Op::new("sharpen")
+                .arg(v.sigma)
+                .opt("threshold", nonzero(v.threshold)),
+            spec::Data::Grayscale(_) => Op::new("grayscale"),
+            spec::Data::Sepia(_) => Op::new("sepia"),
+            spec::Data::Brightness(v) => Op::new("brightness").arg(v.brightness),
+            spec::Data::Saturation(v) => Op::new("saturation").arg(v.saturation),
+            spec::Data::Hue(v) => Op::new("hue").arg(v.degrees),
+            spec::Data::Padding(v) => Op::new("padding")
+                .arg(v.top)
+                .arg(v.right)
+                .arg(v.bottom)
+                .arg(v.left)
+                .opt("bg", v.color.as_ref().map(color_str)),
+            spec::Data::RoundCorners(v) => Op::new("round_corners").arg(v.radius),
+            spec::Data::Text(v) => Op::new("text")
+                .arg(utf8_percent_encode(&v.text, TEXT_ENCODE_SET))
+                .opt("font", (!v.font.is_empty()).then(|| v.font.clone()))
+                .opt("size", nonzero(v.size))
+                .opt("color", v.color.as_ref().map(color_str))
+                .opt("anchor", enum_opt(v.anchor, Anchor::from_i32, Anchor::as_str_name))
+                .opt("x", nonzero(v.x))
+                .opt("y", nonzero(v.y)),
+            spec::Data::Frame(v) => Op::new("frame").arg(v.index),
+        }
+    }
+}
+
+fn size(width: u32, height: u32) -> String {
+    format!("{}x{}", width, height)
+}
+
+fn nonzero<T: Default + PartialEq + ToString>(value: T) -> Option<String> {
+    (value != T::default()).then(|| value.to_string())
+}
+
+// Unknown values are kept as numbers so they still round-trip
+fn enum_str<E>(value: i32, from_i32: fn(i32) -> Option<E>, name: fn(&E) -> &'static str) -> String {
+    match from_i32(value) {
+        Some(e) => name(&e).to_ascii_lowercase(),
+        None => value.to_string(),
+    }
+}
+
+fn enum_opt<E>(value: i32, from_i32: fn(i32) -> Option<E>, name: fn(&E) -> &'static str) -> Option<String> {
+    (value != 0).then(|| enum_str(value, from_i32, name))
+}
+
+fn color_str(color: &Color) -> String {
+    let [r, g, b, a] = color.to_rgba();
+    format!("{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+}
+
+// One transform being read
+struct Parser<'a> {
+    name: &'a str,
+    args: VecDeque<&'a str>,
+    opts: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(item: &'a str) -> Self {
+        let mut parts = item.split(':');
+        let name = parts.next().unwrap_or_default();
+        let (opts, args): (Vec<_>, Vec<_>) = parts.partition(|p| p.contains('='));
+        let opts = opts.into_iter().filter_map(|p| p.split_once('=')).collect();
+        Self {
+            name,
+            args: args.into(),
+            opts,
+        }
+    }
+
+    fn data(&mut self) -> Result<spec::Data> {
+        Ok(match self.name {
+            "resize" => {
+                let (width, height) = self.size_arg()?;
+                spec::Data::Resize(Resize {
+                    width,
+                    height,
+                    rtype: self.enum_opt("type", resize::ResizeType::from_str_name)?,
+                    filter: self.enum_opt("filter", resize::SampleFilter::from_str_name)?,
+                    fit: self.enum_opt("fit", resize::Fit::from_str_name)?,
+                    gravity: self.enum_opt("gravity", resize::Gravity::from_str_name)?,
+                    background: self.color_opt("bg")?,
+                })
+            }
+            "crop" => {
+                let (x1, y1) = self.size_arg()?;
+                let (x2, y2) = self.size_arg()?;
+                spec::Data::Crop(Crop { x1, y1, x2, y2 })
+            }
+            "flipv" => spec::Data::Flipv(Flipv {}),
+            "fliph" => spec::Data::Fliph(Fliph {}),
+            "contrast" => spec::Data::Contrast(Contrast { contrast: self.arg()? }),
+            "filter" => spec::Data::Filter(Filter {
+                filter: self.enum_arg(filter::Filter::from_str_name)?,
+            }),
+            "wm" | "watermark" => {
+                let (x, y) = self.size_arg()?;
+                spec::Data::Watermark(Watermark {
+                    x,
+                    y,
+                    name: self.opt("name")?.unwrap_or_default(),
+                    scale: self.opt("scale")?.unwrap_or_default(),
+                    opacity: self.opt("opacity")?.unwrap_or_default(),
+                    anchor: self.enum_opt("anchor", Anchor::from_str_name)?,
+                    tile: self.opt("tile")?.unwrap_or_default(),
+                })
+            }
+            "rotate" => spec::Data::Rotate(Rotate {
+                angle: self.arg()?,
+                background: self.color_opt("bg")?,
+            }),
+            "blur" => spec::Data::Blur(Blur { radius: self.arg()? }),
+            "sharpen" => spec::Data::Sharpen(Sharpen {
+                sigma: self.arg()?,
+                threshold: self.opt("threshold")?.unwrap_or_default(),
+            }),
+            "grayscale" => spec::Data::Grayscale(Grayscale {}),
+            "sepia" => spec::Data::Sepia(Sepia {}),
+            "brightness" => spec::Data::Brightness(Brightness { brightness: self.arg()? }),
+            "saturation" => spec::Data::Saturation(Saturation { saturation: self.arg()? }),
+            "hue" => spec::Data::Hue(Hue { degrees: self.arg()? }),
+            "padding" => spec::Data::Padding(Padding {
+                top: self.arg()?,
+                right: self.arg()?,
+                bottom: self.arg()?,
+                left: self.arg()?,
+                color: self.color_opt("bg")?,
+            }),
+            "round_corners" | "round" => spec::Data::RoundCorners(RoundCorners { radius: self.arg()? }),
+            "text" => {
+                let text = self.next_arg()?;
+                spec::Data::Text(Text {
+                    text: percent_decode_str(text).decode_utf8()?.into_owned(),
+                    font: self.opt("font")?.unwrap_or_default(),
+                    size: self.opt("size")?.unwrap_or_default(),
+                    color: self.color_opt("color")?,
+                    x: self.opt("x")?.unwrap_or_default(),
+                    y: self.opt("y")?.unwrap_or_default(),
+                    anchor: self.enum_opt("anchor", Anchor::from_str_name)?,
+                })
+            }
+            "frame" => spec::Data::Frame(Frame { index: self.arg()? }),
+            name => bail!("unknown transform {:?}", name),
+        })
+    }
+
+    fn next_arg(&mut self) -> Result<&'a str> {
+        self.args
+            .pop_front()
+            .ok_or_else(|| anyhow!("{} is missing an argument", self.name))
+    }
+
+    fn arg<T: FromStr>(&mut self) -> Result<T> {
+        let arg = self.next_arg()?;
+        arg.parse()
+            .map_err(|_| anyhow!("invalid argument {:?} for {}", arg, self.name))
+    }
+
+    // `WxH`, either side may be left empty for 0
+    fn size_arg(&mut self) -> Result<(u32, u32)> {
+        let arg = self.next_arg()?;
+        let parse = |v: &str| if v.is_empty() { Ok(0) } else { v.parse() };
+        match arg.split_once('x').map(|(w, h)| (parse(w), parse(h))) {
+            Some((Ok(w), Ok(h))) => Ok((w, h)),
+            _ => bail!("invalid size {:?} for {}, expected WIDTHxHEIGHT", arg, self.name),
+        }
+    }
+
+    fn enum_arg<E: Into<i32>>(&mut self, from_str_name: fn(&str) -> Option<E>) -> Result<i32> {
+        let arg = self.next_arg()?;
+        parse_enum(arg, from_str_name).ok_or_else(|| anyhow!("unknown value {:?} for {}", arg, self.name))
+    }
+
+    fn take_opt(&mut self, key: &str) -> Option<&'a str> {
+        let index = self.opts.iter().position(|(k, _)| *k == key)?;
+        Some(self.opts.remove(index).1)
+    }
+
+    fn opt<T: FromStr>(&mut self, key: &str) -> Result<Option<T>> {
+        match self.take_opt(key) {
+            Some(v) => v
+                .parse()
+                .map(Some)
+                .map_err(|_| anyhow!("invalid {} {:?} for {}", key, v, self.name)),
+            None => Ok(None),
+        }
+    }
+
+    fn enum_opt<E: Into<i32>>(&mut self, key: &str, from_str_name: fn(&str) -> Option<E>) -> Result<i32> {
+        match self.take_opt(key) {
+            Some(v) => parse_enum(v, from_str_name)
+                .ok_or_else(|| anyhow!("unknown {} {:?} for {}", key, v, self.name)),
+            None => Ok(0),
+        }
+    }
+
+    fn color_opt(&mut self, key: &str) -> Result<Option<Color>> {
+        match self.take_opt(key) {
+            Some(v) => parse_color(v)
+                .map(Some)
+                .ok_or_else(|| anyhow!("invalid color {:?} for {}, expected RRGGBB or RRGGBBAA", v, self.name)),
+            None => Ok(None),
+        }
+    }
+
+    // anything left over is a mistake rather than something to ignore
+    fn done(self) -> Result<()> {
+        if let Some(arg) = self.args.front() {
+            bail!("unexpected argument {:?} for {}", arg, self.name);
+        }
+        if let Some((key, _)) = self.opts.first() {
+            bail!("unknown option {:?} for {}", key, self.name);
+        }
+        Ok(())
+    }
+}
+
+fn parse_enum<E: Into<i32>>(s: &str, from_str_name: fn(&str) -> Option<E>) -> Option<i32> {
+    from_str_name(&s.to_ascii_uppercase())
+        .map(Into::into)
+        .or_else(|| s.parse().ok())
+}
+
+fn parse_color(s: &str) -> Option<Color> {
+    if !(s.len() == 6 || s.len() == 8) || !s.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(s.get(i..i + 2)?, 16).ok();
+    let alpha = if s.len() == 8 { channel(6)? } else { 255 };
+    Some(Color::new(channel(0)?, channel(2)?, channel(4)?, alpha))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readable_spec_should_parse() {
+        let spec: ImageSpec = "resize:500x800,filter:marine,wm:20x20".parse().unwrap();
+        assert_eq!(
+            spec,
+            ImageSpec::new(vec![
+                Spec::new_resize(500, 800, resize::SampleFilter::Undefined),
+                Spec::new_filter(filter::Filter::Marine),
+                Spec::new_watermark(20, 20),
+            ])
+        );
+    }
+
+    #[test]
+    fn display_should_round_trip() {
+        let spec = ImageSpec::new(vec![
+            Spec::new_frame(1),
+            Spec::new_resize_fit(300, 0, resize::Fit::Cover, resize::Gravity::NorthEast, Some(Color::new(255, 255, 255, 128))),
+            Spec::new_resize_seam_carve(200, 100),
+            Spec::new_rotate(-12.5, None),
+            Spec::new_sharpen(1.5, 2),
+            Spec::new_grayscale(),
+            Spec::new_padding(1, 2, 3, 4, Some(Color::new(0, 0, 0, 255))),
+            Spec::new_tiled_watermark("logo", 16, 0.1, 0.3),
+            Spec::new_text("Hello, World: 100%", "default", 32.0, None, Anchor::Bottom, 0, 8),
+        ])
+        .with_output(output::Format::Webp, 75)
+        .with_metadata(output::Metadata::Keep);
+        let s = spec.to_string();
+        assert_eq!(
+            s,
+            "frame:1,resize:300x0:filter=catmull_rom:fit=cover:gravity=north_east:bg=ffffff80,\
+             resize:200x100:type=seam_carve,rotate:-12.5,sharpen:1.5:threshold=2,grayscale,\
+             padding:1:2:3:4:bg=000000ff,wm:16x16:name=logo:scale=0.1:opacity=0.3:tile=true,\
+             text:Hello%2C World%3A 100%25:font=default:size=32:anchor=bottom:y=8,format:webp:quality=75:metadata=keep"
+        );
+        assert_eq!(s.parse::<ImageSpec>().unwrap(), spec);
+    }
+
+    #[test]
+    fn aliases_and_short_forms_should_parse() {
+        let spec: ImageSpec = "resize:x300:fit=INSIDE,round:8,watermark:0x0:name=logo,rotate:45:bg=ff0000"
+            .parse()
+            .unwrap();
+        assert_eq!(spec.to_string(), "resize:0x300:fit=inside,round_corners:8,wm:0x0:name=logo,rotate:45:bg=ff0000ff");
+    }
+
+    #[test]
+    fn invalid_specs_should_be_rejected() {
+        for (s, err) in [
+            ("zoom:2", "unknown transform \"zoom\""),
+            ("resize:500", "invalid size \"500\" for resize, expected WIDTHxHEIGHT"),
+            ("resize:500x800:fit=stretch", "unknown fit \"stretch\" for resize"),
+            ("blur", "blur is missing an argument"),
+            ("blur:3:4", "unexpected argument \"4\" for blur"),
+            ("hue:90:speed=2", "unknown option \"speed\" for hue"),
+            ("rotate:45:bg=red", "invalid color \"red\" for rotate, expected RRGGBB or RRGGBBAA"),
+            ("format:png,format:jpeg", "format is given twice"),
+        ] {
+            assert_eq!(s.parse::<ImageSpec>().unwrap_err().to_string(), err);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use thiserror::Error;
+use tokio::sync::Semaphore;
+
+#[derive(Debug, Error)]
+pub enum PoolError {
+    #[error("server is busy, {0} requests are already waiting")]
+    Full(usize),
+    #[error("processing failed: {0}")]
+    Failed(String),
+}
+
+impl PoolError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            PoolError::Full(_) => StatusCode::SERVICE_UNAVAILABLE,
+            PoolError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Runs CPU heavy work on tokio's blocking threads, at most `workers` jobs at a time
+/// and with at most `queue` more waiting for their turn
+pub struct Pool {
+    permits: Arc<Semaphore>,
+    workers: usize,
+    queue: usize,
+    waiting: AtomicUsize,
+}
+
+// A place in the queue, given up when the job starts or its request goes away
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Pool {
+    pub fn new(workers: usize, queue: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(workers)),
+            workers,
+            queue,
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    pub async fn run<T, F>(&self, f: F) -> Result<T, PoolError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = match self.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                let _waiting = self.enqueue()?;
+                self.permits
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("the pool semaphore is never closed")
+            }
+        };
+
+        tokio::task::spawn_blocking(move || {
+            // held until the job is done, even when its request stopped waiting for it
+            let _permit = permit;
+            f()
+        })
+        .await
+        .map_err(|e| PoolError::Failed(e.to_string()))
+    }
+
+    // jobs holding a worker, finished or not
+    pub fn running(&self) -> usize {
+        self.workers - self.permits.available_permits()
+    }
+
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+
+    // every worker is busy and the queue is full, so new jobs would be rejected
+    pub fn is_full(&self) -> bool {
+        self.running() == self.workers && self.waiting() >= self.queue
+    }
+
+    fn enqueue(&self) -> Result<Waiting<'_>, PoolError> {
+        self.waiting
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < self.queue).then_some(n + 1))
+            .map_err(|_| PoolError::Full(self.queue))?;
+        Ok(Waiting(&self.waiting))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    async fn until(f: impl Fn() -> bool) {
+        while !f() {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn full_queue_should_be_rejected() {
+        let pool = Arc::new(Pool::new(1, 1));
+        let (tx, rx) = mpsc::channel::<()>();
+
+        let p = pool.clone();
+        let running = tokio::spawn(async move { p.run(move || rx.recv().unwrap()).await });
+        until(|| pool.permits.available_permits() == 0).await;
+        let p = pool.clone();
+        let queued = tokio::spawn(async move { p.run(|| 42).await });
+        until(|| pool.waiting.load(Ordering::SeqCst) == 1).await;
+
+        assert!(pool.is_full());
+        assert_eq!((pool.running(), pool.waiting()), (1, 1));
+        let err = pool.run(|| 0).await.unwrap_err();
+        assert!(matches!(err, PoolError::Full(1)));
+        assert_eq!(err.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        tx.send(()).unwrap();
+        running.await.unwrap().unwrap();
+        assert_eq!(queued.await.unwrap().unwrap(), 42);
+        assert_eq!(pool.waiting.load(Ordering::SeqCst), 0);
+        assert!(!pool.is_full());
+    }
+
+    #[tokio::test]
+    async fn abandoned_job_should_keep_its_worker() {
+        let pool = Pool::new(1, 0);
+        let (tx, rx) = mpsc::channel::<()>();
+        let job = pool.run(move || rx.recv().unwrap());
+        assert!(tokio::time::timeout(Duration::from_millis(20), job).await.is_err());
+        assert_eq!(pool.permits.available_permits(), 0);
+        assert!(matches!(pool.run(|| ()).await, Err(PoolError::Full(0))));
+
+        tx.send(()).unwrap();
+        until(|| pool.permits.available_permits() == 1).await;
+        assert_eq!(pool.run(|| 7).await.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn panics_should_be_reported() {
+        let pool = Pool::new(1, 0);
+        let err = pool.run(|| panic!("boom")).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(pool.run(|| 1).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,114 @@
+use base64::engine::{self, general_purpose};
+use base64::{alphabet, Engine as _};
+use hmac::{Hmac, Mac};
+use percent_encoding::{percent_encode, AsciiSet, CONTROLS};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SIGNATURE_ENGINE: engine::GeneralPurpose =
+    engine::GeneralPurpose::new(&alphabet::URL_SAFE, general_purpose::NO_PAD);
+const URL_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'/')
+    .add(b'?')
+    .add(b'#')
+    .add(b'=')
+    .add(b'&')
+    .add(b'%');
+
+// Signs the spec and the url with HMAC-SHA256, so only URLs produced with the server secret are served
+pub struct UrlSigner {
+    secret: Vec<u8>,
+}
+
+impl UrlSigner {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+
+    pub fn sign(&self, spec: &str, url: &str) -> String {
+        SIGNATURE_ENGINE.encode(self.mac(spec, url).finalize().into_bytes())
+    }
+
+    // constant-time comparison through `verify_slice`
+    pub fn verify(&self, spec: &str, url: &str, signature: &str) -> bool {
+        match SIGNATURE_ENGINE.decode(signature) {
+            Ok(tag) => self.mac(spec, url).verify_slice(&tag).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    // Path to request: `/image/<spec>/<url>?s=<signature>`, spec and url percent encoded
+    pub fn signed_path(&self, spec: &str, url: &str) -> String {
+        format!("{}?s={}", image_path(spec, url), self.sign(spec, url))
+    }
+
+    // Each field is prefixed with its length, so no two (spec, url) pairs give the same input;
+    // a separator could not do that, since both can contain any character
+    fn mac(&self, spec: &str, url: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        for field in [spec, url] {
+            mac.update(&(field.len() as u64).to_be_bytes());
+            mac.update(field.as_bytes());
+        }
+        mac
+    }
+}
+
+// `/image/<spec>/<url>` without a signature, for servers running without a secret
+pub fn image_path(spec: &str, url: &str) -> String {
+    format!(
+        "/image/{}/{}",
+        percent_encode(spec.as_bytes(), URL_ENCODE_SET),
+        percent_encode(url.as_bytes(), URL_ENCODE_SET)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://example.com/a.jpg?w=1&h=2";
+
+    #[test]
+    fn signed_url_could_be_verified() {
+        let signer = UrlSigner::new("secret");
+        let signature = signer.sign("CgoKCAj0AxCgBiAD", URL);
+        assert!(signer.verify("CgoKCAj0AxCgBiAD", URL, &signature));
+    }
+
+    #[test]
+    fn tampered_url_is_rejected() {
+        let signer = UrlSigner::new("secret");
+        let signature = signer.sign("CgoKCAj0AxCgBiAD", URL);
+        assert!(!signer.verify("CgoKCAj0AxCgBiAE", URL, &signature));
+        assert!(!signer.verify("CgoKCAj0AxCgBiAD", "https://example.com/b.jpg", &signature));
+        assert!(!UrlSigner::new("other").verify("CgoKCAj0AxCgBiAD", URL, &signature));
+        assert!(!signer.verify("CgoKCAj0AxCgBiAD", URL, "not base64!"));
+    }
+
+    #[test]
+    fn moving_text_between_spec_and_url_is_rejected() {
+        let signer = UrlSigner::new("secret");
+        let signature = signer.sign("text:a/b", "https://example.com/a.jpg");
+        assert!(!signer.verify("text:a", "b/https://example.com/a.jpg", &signature));
+        assert!(!signer.verify("text:a/b/https:", "/example.com/a.jpg", &signature));
+    }
+
+    #[test]
+    fn signed_path_encodes_url() {
+        let signer = UrlSigner::new("secret");
+        let path = signer.signed_path("spec", URL);
+        assert!(path.starts_with("/image/spec/https:%2F%2Fexample.com%2Fa.jpg%3Fw%3D1%26h%3D2?s="));
+    }
+
+    #[test]
+    fn signed_path_encodes_readable_spec() {
+        let signer = UrlSigner::new("secret");
+        let path = signer.signed_path("text:50%25 off,blur:2", URL);
+        assert!(path.starts_with("/image/text:50%2525%20off,blur:2/"));
+    }
+}
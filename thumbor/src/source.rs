@@ -0,0 +1,302 @@
+use axum::http::StatusCode;
+use bytes::{Bytes, BytesMut};
+use reqwest::{redirect, header, Url};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::info;
+
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Debug, Clone, Error)]
+pub enum FetchError {
+    #[error("invalid source url: {0}")]
+    InvalidUrl(String),
+    #[error("scheme {0} is not allowed")]
+    SchemeNotAllowed(String),
+    #[error("host {0} is not allowed")]
+    HostNotAllowed(String),
+    #[error("address {0} is not allowed")]
+    ForbiddenAddress(IpAddr),
+    #[error("source is larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("source download timed out")]
+    Timeout,
+    #[error("failed to fetch source: {0}")]
+    Upstream(String),
+}
+
+impl FetchError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            FetchError::InvalidUrl(_) | FetchError::SchemeNotAllowed(_) => StatusCode::BAD_REQUEST,
+            FetchError::HostNotAllowed(_) => StatusCode::FORBIDDEN,
+            FetchError::ForbiddenAddress(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            FetchError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            FetchError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            FetchError::Upstream(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+// A source image, with the origin's Last-Modified when it sent one
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub data: Bytes,
+    pub last_modified: Option<String>,
+}
+
+impl From<Bytes> for Source {
+    fn from(data: Bytes) -> Self {
+        Self {
+            data,
+            last_modified: None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            FetchError::Timeout
+        } else {
+            FetchError::Upstream(e.to_string())
+        }
+    }
+}
+
+// Which sources thumbor may download, and how much of them
+#[derive(Debug, Clone)]
+pub struct SourcePolicy {
+    pub allowed_schemes: Vec<String>,
+    // empty allows any host, `*.example.com` allows every subdomain
+    pub allowed_hosts: Vec<String>,
+    // loopback, private, link-local (cloud metadata) and similar addresses
+    pub allow_private: bool,
+    pub max_size: usize,
+    pub timeout: Duration,
+}
+
+impl Default for SourcePolicy {
+    fn default() -> Self {
+        Self {
+            allowed_schemes: vec!["http".into(), "https".into()],
+            allowed_hosts: vec![],
+            allow_private: false,
+            max_size: 20 * 1024 * 1024,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl SourcePolicy {
+    pub async fn fetch(&self, url: &str) -> Result<Source, FetchError> {
+        tokio::time::timeout(self.timeout, self.fetch_inner(url))
+            .await
+            .map_err(|_| FetchError::Timeout)?
+    }
+
+    async fn fetch_inner(&self, url: &str) -> Result<Source, FetchError> {
+        let mut url = Url::parse(url).map_err(|e| FetchError::InvalidUrl(e.to_string()))?;
+
+        // redirects are followed by hand, so every hop goes through the same checks
+        for _ in 0..=MAX_REDIRECTS {
+            let (host, addr) = self.check(&url).await?;
+            let client = reqwest::Client::builder()
+                .redirect(redirect::Policy::none())
+                .timeout(self.timeout)
+                // connect to the address we validated, not whatever DNS says next
+                .resolve(&host, addr)
+                .build()?;
+
+            let resp = client.get(url.clone()).send().await?;
+            if resp.status().is_redirection() {
+                let location = resp
+                    .headers()
+                    .get(header::LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .ok_or_else(|| FetchError::Upstream("redirect without location".into()))?;
+                url = url
+                    .join(location)
+                    .map_err(|e| FetchError::InvalidUrl(e.to_string()))?;
+                info!("Follow redirect to {}", url);
+                continue;
+            }
+
+            let resp = resp.error_for_status()?;
+            let last_modified = resp
+                .headers()
+                .get(header::LAST_MODIFIED)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from);
+            let data = self.read_body(resp).await?;
+            return Ok(Source { data, last_modified });
+        }
+
+        Err(FetchError::Upstream("too many redirects".into()))
+    }
+
+    async fn check(&self, url: &Url) -> Result<(String, SocketAddr), FetchError> {
+        if !self.allowed_schemes.iter().any(|s| s == url.scheme()) {
+            return Err(FetchError::SchemeNotAllowed(url.scheme().into()));
+        }
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| FetchError::InvalidUrl("missing host".into()))?
+            .to_string();
+        if !self.allowed_hosts.is_empty() && !self.allowed_hosts.iter().any(|p| host_matches(p, &host)) {
+            return Err(FetchError::HostNotAllowed(host));
+        }
+
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.trim_matches(|c| c == '[' || c == ']'), port))
+            .await
+            .map_err(|e| FetchError::Upstream(e.to_string()))?
+            .collect();
+
+        // every resolved address must be public, otherwise a second lookup could pick a private one
+        if !self.allow_private {
+            if let Some(addr) = addrs.iter().find(|a| !is_public(a.ip())) {
+                return Err(FetchError::ForbiddenAddress(addr.ip()));
+            }
+        }
+
+        let addr = addrs
+            .into_iter()
+            .next()
+            .ok_or_else(|| FetchError::Upstream(format!("cannot resolve {}", host)))?;
+        Ok((host, addr))
+    }
+
+    async fn read_body(&self, mut resp: reqwest::Response) -> Result<Bytes, FetchError> {
+        if resp.content_length().is_some_and(|len| len as usize > self.max_size) {
+            return Err(FetchError::TooLarge(self.max_size));
+        }
+
+        let mut data = BytesMut::new();
+        while let Some(chunk) = resp.chunk().await? {
+            if data.len() + chunk.len() > self.max_size {
+                return Err(FetchError::TooLarge(self.max_size));
+            }
+            data.extend_from_slice(&chunk);
+        }
+        Ok(data.freeze())
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let (pattern, host) = (pattern.to_ascii_lowercase(), host.to_ascii_lowercase());
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped().or_else(|| embedded_v4(ip)) {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // 0.0.0.0/8, the 100.64.0.0/10 carrier-grade NAT range and 198.18.0.0/15 for benchmarking
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 198 && (b & 0xfe) == 18))
+}
+
+// The v4 address that NAT64 (64:ff9b::/96), 6to4 (2002::/16) and IPv4-compatible (::/96)
+// addresses reach, which is what has to be public
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let s = ip.segments();
+    let low = Ipv4Addr::new((s[6] >> 8) as u8, s[6] as u8, (s[7] >> 8) as u8, s[7] as u8);
+    match s {
+        [0x64, 0xff9b, 0, 0, 0, 0, ..] | [0, 0, 0, 0, 0, 0, ..] => Some(low),
+        [0x2002, ..] => Some(Ipv4Addr::new((s[1] >> 8) as u8, s[1] as u8, (s[2] >> 8) as u8, s[2] as u8)),
+        _ => None,
+    }
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // unique local fc00::/7 and link-local fe80::/10
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.64.0.1",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "198.18.0.1",
+            "198.19.255.255",
+            "64:ff9b::10.0.0.1",
+            "2002:7f00:1::1",
+            "::192.168.1.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        assert!(is_public("93.184.216.34".parse().unwrap()));
+        assert!(is_public("2606:2800:220:1::1".parse().unwrap()));
+        assert!(is_public("198.20.0.1".parse().unwrap()));
+        assert!(is_public("64:ff9b::5db8:d822".parse().unwrap()));
+    }
+
+    #[test]
+    fn host_patterns_match() {
+        assert!(host_matches("example.com", "example.com"));
+        assert!(host_matches("*.example.com", "img.example.com"));
+        assert!(!host_matches("*.example.com", "example.com"));
+        assert!(!host_matches("*.example.com", "badexample.com"));
+        assert!(!host_matches("example.com", "example.com.evil.io"));
+        assert!(host_matches("*.Example.com", "IMG.example.COM"));
+    }
+
+    #[tokio::test]
+    async fn rejected_sources_have_distinct_status() {
+        let policy = SourcePolicy {
+            allowed_hosts: vec!["127.0.0.1".into(), "example.com".into()],
+            ..Default::default()
+        };
+        let status = |r: Result<Source, FetchError>| r.unwrap_err().status();
+
+        assert_eq!(status(policy.fetch("ftp://example.com/a.png").await), StatusCode::BAD_REQUEST);
+        assert_eq!(status(policy.fetch("http://other.com/a.png").await), StatusCode::FORBIDDEN);
+        assert_eq!(
+            status(policy.fetch("http://127.0.0.1/a.png").await),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs;
+
+use super::{check_id, content_id, Storage, StorageError};
+
+// One file per original, named by its id
+pub struct FsStorage {
+    dir: PathBuf,
+}
+
+impl FsStorage {
+    pub async fn open(dir: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).await?;
+        Ok(Self { dir })
+    }
+}
+
+#[async_trait]
+impl Storage for FsStorage {
+    async fn put(&self, data: Bytes) -> Result<String, StorageError> {
+        let id = content_id(&data);
+        let path = self.dir.join(&id);
+        if fs::metadata(&path).await.is_err() {
+            write_atomic(&path, &data).await?;
+        }
+        Ok(id)
+    }
+
+    async fn get(&self, id: &str) -> Result<Bytes, StorageError> {
+        check_id(id)?;
+        match fs::read(self.dir.join(id)).await {
+            Ok(data) => Ok(data.into()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(StorageError::NotFound(id.to_owned())),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+// Written aside and renamed, so readers never see a partial file. Each write gets its own
+// temporary file, so identical uploads running at the same time do not trip over each other
+async fn write_atomic(path: &Path, data: &[u8]) -> Result<(), StorageError> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let tmp = path.with_extension(format!("{}.tmp", NEXT.fetch_add(1, Ordering::Relaxed)));
+    let result = match fs::write(&tmp, data).await {
+        Ok(()) => fs::rename(&tmp, path).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp).await;
+            // the same content got there first, which is just as good
+            match fs::metadata(path).await {
+                Ok(_) => Ok(()),
+                Err(_) => Err(e.into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("thumbor-storage-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn stored_image_should_be_readable() {
+        let storage = FsStorage::open(temp_dir("roundtrip")).await.unwrap();
+        let id = storage.put(Bytes::from_static(b"image")).await.unwrap();
+        assert_eq!(storage.get(&id).await.unwrap(), Bytes::from_static(b"image"));
+        assert_eq!(storage.put(Bytes::from_static(b"image")).await.unwrap(), id);
+    }
+
+    #[tokio::test]
+    async fn identical_uploads_may_overlap() {
+        let dir = temp_dir("overlap");
+        let storage = std::sync::Arc::new(FsStorage::open(&dir).await.unwrap());
+        let puts: Vec<_> = (0..8)
+            .map(|_| {
+                let storage = storage.clone();
+                tokio::spawn(async move { storage.put(Bytes::from_static(b"same image")).await })
+            })
+            .collect();
+        for put in puts {
+            assert_eq!(put.await.unwrap().unwrap(), content_id(b"same image"));
+        }
+        // nothing but the image itself is left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn unknown_or_invalid_ids_should_fail() {
+        let storage = FsStorage::open(temp_dir("missing")).await.unwrap();
+        let missing = content_id(b"never stored");
+        assert!(matches!(storage.get(&missing).await, Err(StorageError::NotFound(_))));
+        for id in ["../etc/passwd", "ABC", ""] {
+            assert!(matches!(storage.get(id).await, Err(StorageError::InvalidId(_))), "{}", id);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+mod fs;
+pub use fs::FsStorage;
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+// `/image/<spec>/local:<id>` processes a stored original instead of downloading one
+pub const LOCAL_PREFIX: &str = "local:";
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("invalid image id {0:?}")]
+    InvalidId(String),
+    #[error("image {0} does not exist")]
+    NotFound(String),
+    #[error("storage failed: {0}")]
+    Io(String),
+}
+
+impl StorageError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            StorageError::InvalidId(_) => StatusCode::BAD_REQUEST,
+            StorageError::NotFound(_) => StatusCode::NOT_FOUND,
+            StorageError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        StorageError::Io(e.to_string())
+    }
+}
+
+/// Where uploaded originals live. Ids are the sha256 of the content, so uploading
+/// the same image twice returns the same id
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn put(&self, data: Bytes) -> Result<String, StorageError>;
+    async fn get(&self, id: &str) -> Result<Bytes, StorageError>;
+}
+
+pub fn content_id(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+// Ids end up in paths and keys, so only accept what `content_id` produces
+pub fn check_id(id: &str) -> Result<(), StorageError> {
+    if id.len() == 64 && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        Ok(())
+    } else {
+        Err(StorageError::InvalidId(id.to_owned()))
+    }
+}